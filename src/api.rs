@@ -0,0 +1,379 @@
+// HTTP API on localhost for scripts and dashboards. Reads are answered from a
+// snapshot of the schedule, edits are sent to the window as ScheduleMessages and
+// applied on its next tick, so they go through the same handling as the GUI.
+//
+//     GET    /activities            all activities
+//     GET    /schedule              the whole schedule, as in .json files
+//     GET    /today                 today's blocks
+//     GET    /now, /next            the running and the next planned block, or null
+//     GET    /calendar.ics          the timetable as an iCalendar feed to subscribe to
+//     POST   /activities            {"name": ..., "url": ...} adds an activity
+//     DELETE /activities/<id>       removes an activity
+//     PUT    /plan/<day>/<block>    {"activity": <id> or null} fills a slot
+//
+// Days and blocks are counted from 0, like in the assignments of /schedule. The
+// daemon serves the same routes without a window, so edits are refused there.
+//
+// Web pages can send requests to localhost too, and read the answers after pointing
+// their own domain at 127.0.0.1. So the Host has to name this server, and edits have
+// to be JSON, which pages can only send after a CORS preflight that is never allowed.
+
+use std::io::Read;
+use std::sync::mpsc::Sender;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use chrono::NaiveDateTime;
+use serde_json::{json, Value};
+use tiny_http::Method;
+
+use crate::clock::{self, Occurrence};
+use crate::ical;
+use crate::import;
+use crate::storage::PersistentData;
+use crate::text_format::TextSchedule;
+use crate::ScheduleMessage;
+
+pub const DEFAULT_PORT: u16 = 7734;
+
+// Edits are small JSON objects, anything longer isn't read into memory
+const MAX_BODY: u64 = 64 * 1024;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Savefile, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ApiSettings {
+    #[savefile_versions = "13.."]
+    pub enabled: bool,
+    #[savefile_versions = "13.."]
+    pub port: u16,
+}
+
+impl Default for ApiSettings {
+    fn default() -> ApiSettings {
+        ApiSettings { enabled: false, port: DEFAULT_PORT }
+    }
+}
+
+// Status code and JSON body
+type Reply = (u16, Value);
+
+fn error(status: u16, message: &str) -> Reply {
+    (status, json!({ "error": message }))
+}
+
+fn accepted() -> Reply {
+    (202, json!({ "status": "accepted" }))
+}
+
+fn occurrence_json(data: &PersistentData, occurrence: &Occurrence) -> Value {
+    let activity = occurrence.activity
+        .and_then(|id| { data.activities.iter().find(|activity| { activity.id == id }) });
+
+    json!({
+        "day": occurrence.day,
+        "block": occurrence.block,
+        "start": occurrence.start.format("%Y-%m-%dT%H:%M:%S").to_string(),
+        "end": occurrence.end.format("%Y-%m-%dT%H:%M:%S").to_string(),
+        "activity": activity,
+    })
+}
+
+fn today(data: &PersistentData, now: &NaiveDateTime) -> Value {
+    let (day, _) = clock::plan_position(now);
+    let monday = clock::week_start(now.date());
+    let slots = match data.slots.get(day) {
+        Some(slots) => slots,
+        None => return json!([]),
+    };
+
+    let blocks: Vec<Value> = slots.iter().zip(data.block_times.iter()).enumerate()
+        .map(|(block, (activity, time))| {
+            let (start, end) = clock::block_occurrence(monday, day, time);
+            occurrence_json(data, &Occurrence { day, block, activity: *activity, start, end })
+        })
+        .collect();
+
+    json!(blocks)
+}
+
+fn add_activity(data: &PersistentData, body: &str, messages: &Sender<ScheduleMessage>) -> Reply {
+    #[derive(serde::Deserialize)]
+    struct NewActivity {
+        name: String,
+        #[serde(default)]
+        url: String,
+    }
+
+    let activity: NewActivity = match serde_json::from_str(body) {
+        Ok(activity) => activity,
+        Err(err) => return error(400, &err.to_string()),
+    };
+
+    let name = activity.name.trim();
+    if name.is_empty() {
+        return error(400, "The name is empty");
+    }
+    if data.activities.iter().any(|activity| { import::same_name(&activity.name, name) }) {
+        return error(409, "There already is an activity with this name");
+    }
+
+    messages.send(ScheduleMessage::AddActivity(name.to_string(), activity.url.trim().to_string())).ok();
+    accepted()
+}
+
+fn assign(data: &PersistentData, day: &str, block: &str, body: &str,
+          messages: &Sender<ScheduleMessage>) -> Reply {
+    #[derive(serde::Deserialize)]
+    struct Assignment {
+        activity: Option<usize>,
+    }
+
+    let day = day.parse::<usize>().ok().filter(|day| { *day < data.slots.len() });
+    let block = block.parse::<usize>().ok().filter(|block| { *block < data.block_times.len() });
+    let (day, block) = match (day, block) {
+        (Some(day), Some(block)) => (day, block),
+        _ => return error(404, "The plan has no such slot"),
+    };
+
+    let assignment: Assignment = match serde_json::from_str(body) {
+        Ok(assignment) => assignment,
+        Err(err) => return error(400, &err.to_string()),
+    };
+
+    if let Some(id) = assignment.activity {
+        if !data.activities.iter().any(|activity| { activity.id == id }) {
+            return error(404, "No activity with this ID");
+        }
+    }
+
+    messages.send(ScheduleMessage::ActivityChosen(day, block, assignment.activity)).ok();
+    accepted()
+}
+
+// Refusal of a request which may come from a web page
+fn check_origin(method: &Method, header: impl Fn(&str) -> Option<String>, port: u16) -> Option<Reply> {
+    let local = [format!("127.0.0.1:{}", port), format!("localhost:{}", port)];
+    let host = header("Host").unwrap_or_default().to_lowercase();
+    if !local.contains(&host) {
+        return Some(error(403, "Use http://127.0.0.1:<port> or http://localhost:<port>"));
+    }
+
+    if let Some(origin) = header("Origin") {
+        if !local.iter().any(|local| { origin.to_lowercase() == format!("http://{}", local) }) {
+            return Some(error(403, "Requests from web pages are not allowed"));
+        }
+    }
+
+    let json = header("Content-Type")
+        .map(|content_type| { content_type.split(';').next().unwrap_or("").trim().to_lowercase() })
+        .filter(|content_type| { content_type == "application/json" });
+    if method != &Method::Get && json.is_none() {
+        return Some(error(415, "Edits need Content-Type: application/json"));
+    }
+
+    None
+}
+
+fn route(method: &Method, path: &str, body: &str, data: &PersistentData,
+         messages: Option<&Sender<ScheduleMessage>>) -> Reply {
+    let segments: Vec<&str> = path.split('/').filter(|segment| { !segment.is_empty() }).collect();
+    let now = clock::local_now();
+
+    let messages = match (method, messages) {
+        (Method::Get, _) => None,
+        (_, Some(messages)) => Some(messages),
+        (_, None) => return error(405, "The schedule is read-only here, edit it in the window or its file"),
+    };
+
+    match (method, segments.as_slice(), messages) {
+        (Method::Get, ["activities"], _) => (200, json!(data.activities)),
+        (Method::Get, ["schedule"], _) => (200, json!(TextSchedule::from(data))),
+        (Method::Get, ["today"], _) => (200, today(data, &now)),
+        (Method::Get, ["now"], _) => {
+            let current = clock::current(data, &now);
+            (200, current.map(|current| { occurrence_json(data, &current) }).unwrap_or(Value::Null))
+        }
+        (Method::Get, ["next"], _) => {
+            let next = clock::next(data, &now);
+            (200, next.map(|next| { occurrence_json(data, &next) }).unwrap_or(Value::Null))
+        }
+
+        (Method::Post, ["activities"], Some(messages)) => add_activity(data, body, messages),
+        (Method::Delete, ["activities", id], Some(messages)) => {
+            match id.parse::<usize>() {
+                Ok(id) if data.activities.iter().any(|activity| { activity.id == id }) => {
+                    messages.send(ScheduleMessage::RemoveActivity(id)).ok();
+                    accepted()
+                }
+                _ => error(404, "No activity with this ID"),
+            }
+        }
+        (Method::Put, ["plan", day, block], Some(messages)) => assign(data, day, block, body, messages),
+
+        _ => error(404, "Not found"),
+    }
+}
+
+fn json_response(status: u16, reply: Value) -> tiny_http::Response<std::io::Cursor<Vec<u8>>> {
+    let header = tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+    tiny_http::Response::from_string(reply.to_string())
+        .with_status_code(status)
+        .with_header(header)
+}
+
+// At most MAX_BODY bytes of UTF-8
+fn read_body(reader: impl Read) -> Result<String, Reply> {
+    let mut body = vec![];
+    reader.take(MAX_BODY + 1).read_to_end(&mut body).map_err(|err| { error(400, &err.to_string()) })?;
+    if body.len() as u64 > MAX_BODY {
+        return Err(error(413, "The request body is too long"));
+    }
+
+    String::from_utf8(body).map_err(|_| { error(400, "The request body is not UTF-8") })
+}
+
+fn handle(mut request: tiny_http::Request, port: u16, snapshot: &Mutex<PersistentData>,
+          messages: Option<&Sender<ScheduleMessage>>) {
+    let path = request.url().split('?').next().unwrap_or("").to_string();
+
+    let header = |name: &str| {
+        request.headers().iter()
+            .find(|header| { header.field.as_str().as_str().eq_ignore_ascii_case(name) })
+            .map(|header| { header.value.as_str().to_string() })
+    };
+    if let Some((status, reply)) = check_origin(request.method(), header, port) {
+        request.respond(json_response(status, reply)).ok();
+        return;
+    }
+
+    // Built on every request, so subscribed clients pick up edits on their next refresh
+    if request.method() == &Method::Get && path == "/calendar.ics" {
+        let calendar = ical::export(&snapshot.lock().unwrap());
+        let header = tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"text/calendar; charset=utf-8"[..]).unwrap();
+        request.respond(tiny_http::Response::from_string(calendar).with_header(header)).ok();
+        return;
+    }
+
+    let (status, reply) = match read_body(request.as_reader()) {
+        Ok(body) => route(request.method(), &path, &body, &snapshot.lock().unwrap(), messages),
+        Err(reply) => reply,
+    };
+
+    request.respond(json_response(status, reply)).ok();
+}
+
+// Stops listening when dropped
+pub struct Server {
+    server: Arc<tiny_http::Server>,
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.server.unblock();
+    }
+}
+
+// Without `messages` the API is read-only
+pub fn start(port: u16, snapshot: Arc<Mutex<PersistentData>>,
+             messages: Option<Sender<ScheduleMessage>>) -> Result<Server, String> {
+    let server = tiny_http::Server::http(("127.0.0.1", port))
+        .map_err(|err| { format!("Failed to serve the API on port {}: {}", port, err) })?;
+
+    let server = Arc::new(server);
+    let worker = server.clone();
+    thread::spawn(move || {
+        for request in worker.incoming_requests() {
+            handle(request, port, &snapshot, messages.as_ref());
+        }
+    });
+
+    Ok(Server { server })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc;
+
+    fn headers<'a>(pairs: &'a [(&'a str, &'a str)]) -> impl Fn(&str) -> Option<String> + 'a {
+        move |name: &str| {
+            pairs.iter()
+                .find(|(key, _)| { key.eq_ignore_ascii_case(name) })
+                .map(|(_, value)| { value.to_string() })
+        }
+    }
+
+    fn refusal(method: Method, pairs: &[(&str, &str)]) -> Option<u16> {
+        check_origin(&method, headers(pairs), DEFAULT_PORT).map(|(status, _)| { status })
+    }
+
+    #[test]
+    fn accepts_local_clients() {
+        assert_eq!(refusal(Method::Get, &[("Host", "127.0.0.1:7734")]), None);
+        assert_eq!(refusal(Method::Get, &[("host", "LOCALHOST:7734"), ("Origin", "http://localhost:7734")]), None);
+        assert_eq!(refusal(Method::Post, &[("Host", "localhost:7734"),
+                                           ("Content-Type", "application/json; charset=utf-8")]), None);
+    }
+
+    #[test]
+    fn refuses_web_pages() {
+        // DNS rebinding keeps the attacker's host name
+        assert_eq!(refusal(Method::Get, &[("Host", "evil.example:7734")]), Some(403));
+        assert_eq!(refusal(Method::Get, &[("Host", "127.0.0.1:8080")]), Some(403));
+        assert_eq!(refusal(Method::Get, &[]), Some(403));
+        assert_eq!(refusal(Method::Get, &[("Host", "127.0.0.1:7734"), ("Origin", "https://evil.example")]),
+                   Some(403));
+
+        // Simple requests, which need no preflight
+        assert_eq!(refusal(Method::Post, &[("Host", "127.0.0.1:7734"), ("Content-Type", "text/plain")]),
+                   Some(415));
+        assert_eq!(refusal(Method::Delete, &[("Host", "127.0.0.1:7734")]), Some(415));
+    }
+
+    #[test]
+    fn limits_the_body() {
+        let body = |text: &[u8]| { read_body(text).map_err(|(status, _)| { status }) };
+        assert_eq!(body(br#"{"activity": 1}"#), Ok(String::from(r#"{"activity": 1}"#)));
+        assert_eq!(body(b""), Ok(String::new()));
+        assert_eq!(body(b"\xff"), Err(400));
+
+        let long = vec![b' '; MAX_BODY as usize];
+        assert_eq!(body(&long).map(|body| { body.len() }), Ok(long.len()));
+        assert_eq!(body(&[&long[..], b" "].concat()), Err(413));
+    }
+
+    #[test]
+    fn routes_edits_to_the_window() {
+        let mut data = PersistentData::new();
+        data.activities.push(crate::storage::Activity { name: String::from("Math"), ..Default::default() });
+        data.slots = vec![vec![None]];
+        data.block_times = vec![crate::storage::BlockTime { start: 8 * 60, end: 9 * 60 }];
+
+        let (tx, rx) = mpsc::channel();
+        let body = r#"{"name": " Art ", "url": "https://example.com"}"#;
+        assert_eq!(route(&Method::Post, "/activities", body, &data, Some(&tx)).0, 202);
+        assert_eq!(route(&Method::Post, "/activities", r#"{"name": "math"}"#, &data, Some(&tx)).0, 409);
+        assert_eq!(route(&Method::Put, "/plan/0/0", r#"{"activity": 0}"#, &data, Some(&tx)).0, 202);
+        assert_eq!(route(&Method::Put, "/plan/1/0", r#"{"activity": 0}"#, &data, Some(&tx)).0, 404);
+
+        assert_eq!(rx.try_recv().unwrap(),
+                   ScheduleMessage::AddActivity(String::from("Art"), String::from("https://example.com")));
+        assert_eq!(rx.try_recv().unwrap(), ScheduleMessage::ActivityChosen(0, 0, Some(0)));
+        assert!(rx.try_recv().is_err());
+
+        assert_eq!(route(&Method::Post, "/activities", body, &data, None).0, 405);
+    }
+
+    #[test]
+    fn hides_the_calendar_password() {
+        let mut data = PersistentData::new();
+        data.caldav.username = String::from("me");
+        data.caldav.password = String::from("secret");
+
+        let (status, schedule) = route(&Method::Get, "/schedule", "", &data, None);
+        assert_eq!(status, 200);
+        assert_eq!(schedule["caldav"]["username"], "me");
+        assert!(schedule["caldav"].get("password").is_none());
+        assert!(!schedule.to_string().contains("secret"));
+    }
+}
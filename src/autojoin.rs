@@ -0,0 +1,115 @@
+// Opening meeting links on their own shortly before a block starts. Each launch is
+// announced by a countdown during which it can be cancelled, and every occurrence
+// of a block is joined at most once.
+
+use chrono::NaiveDateTime;
+
+use crate::storage::{Activity, PersistentData};
+use crate::trigger::{Scheduler, Trigger};
+
+pub const DEFAULT_MINUTES: u32 = 2;
+pub const MAX_MINUTES: u32 = 30;
+
+// Seconds a launch is announced before it happens
+pub const COUNTDOWN_SECONDS: i64 = 60;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Savefile, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct AutoJoinSettings {
+    // Join every activity, not only those which opted in themselves
+    #[savefile_versions = "11.."]
+    pub enabled: bool,
+
+    // Minutes before the start of the block
+    #[savefile_versions = "11.."]
+    pub minutes: u32,
+}
+
+impl Default for AutoJoinSettings {
+    fn default() -> AutoJoinSettings {
+        AutoJoinSettings { enabled: false, minutes: DEFAULT_MINUTES }
+    }
+}
+
+// Minutes before the start the activity is joined, None if it isn't joined automatically
+pub fn lead_minutes(settings: &AutoJoinSettings, activity: &Activity) -> Option<u32> {
+    match (activity.autojoin, settings.enabled) {
+        (Some(minutes), _) => Some(minutes),
+        (None, true) => Some(settings.minutes),
+        (None, false) => None,
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct AutoJoiner {
+    scheduler: Scheduler,
+}
+
+impl AutoJoiner {
+    pub fn new() -> AutoJoiner {
+        AutoJoiner::default()
+    }
+
+    // Next launch within the countdown, to be announced with a way to cancel it
+    pub fn countdown(&self, data: &PersistentData, now: &NaiveDateTime) -> Option<Trigger> {
+        self.scheduler.next_within(data, now, COUNTDOWN_SECONDS,
+                                   |activity| { lead_minutes(&data.autojoin, activity) })
+    }
+
+    // Launches whose time has come. If the machine was asleep at the launch time,
+    // the block is only joined if it hasn't started yet.
+    pub fn due(&mut self, data: &PersistentData, now: &NaiveDateTime) -> Vec<Trigger> {
+        self.scheduler.due(data, now, |activity| { lead_minutes(&data.autojoin, activity) })
+    }
+
+    pub fn cancel(&mut self, join: &Trigger) {
+        self.scheduler.dismiss(join);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::BlockTime;
+    use chrono::NaiveDateTime;
+
+    // Math on Mondays 09:00-10:00, and Art after it, which opted in for 2 minutes
+    fn data() -> PersistentData {
+        let mut data = PersistentData::new();
+        data.activities.push(Activity { name: String::from("Math"), id: 0, ..Activity::default() });
+        data.activities.push(Activity { name: String::from("Art"), id: 1, autojoin: Some(2), ..Activity::default() });
+        data.block_times = vec![BlockTime { start: 9 * 60, end: 10 * 60 }, BlockTime { start: 10 * 60, end: 11 * 60 }];
+        data.slots = vec![vec![Some(0), Some(1)]];
+        data
+    }
+
+    fn monday(time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(&format!("2021-02-01 {}", time), "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn joins_activities_which_opted_in() {
+        let mut data = data();
+        let mut joiner = AutoJoiner::new();
+        joiner.due(&data, &monday("08:54:59"));
+        assert!(joiner.due(&data, &monday("08:55:00")).is_empty());
+        assert_eq!(joiner.countdown(&data, &monday("09:57:30")).map(|join| { join.activity }), Some(1));
+
+        data.autojoin = AutoJoinSettings { enabled: true, minutes: 5 };
+        let mut joiner = AutoJoiner::new();
+        joiner.due(&data, &monday("08:54:59"));
+        assert_eq!(joiner.due(&data, &monday("08:55:00")).len(), 1);
+    }
+
+    #[test]
+    fn honours_cancel() {
+        let data = data();
+        let mut joiner = AutoJoiner::new();
+        joiner.due(&data, &monday("09:57:00"));
+
+        let join = joiner.countdown(&data, &monday("09:57:30")).unwrap();
+        joiner.cancel(&join);
+        assert!(joiner.countdown(&data, &monday("09:57:31")).is_none());
+        assert!(joiner.due(&data, &monday("09:58:00")).is_empty());
+    }
+}
@@ -0,0 +1,121 @@
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::storage::{self, PersistentData};
+
+// Wait this long after the last edit before writing
+const DEBOUNCE: Duration = Duration::from_secs(2);
+
+// But never keep an edit unsaved for longer than this
+const MAX_DELAY: Duration = Duration::from_secs(10);
+
+enum Command {
+    Save(Box<PersistentData>),
+    Flush(mpsc::Sender<()>),
+    Stop,
+}
+
+// Writes the schedule from a background thread, so that a burst of edits costs one write
+pub struct Autosaver {
+    sender: mpsc::Sender<Command>,
+    worker: Option<thread::JoinHandle<()>>,
+}
+
+impl Autosaver {
+    pub fn new(path: String) -> Autosaver {
+        let (sender, receiver) = mpsc::channel();
+        let worker = thread::spawn(move || { run(path, receiver) });
+
+        Autosaver {
+            sender,
+            worker: Some(worker),
+        }
+    }
+
+    // Schedule a write of the given state
+    pub fn save(&self, data: PersistentData) {
+        self.sender.send(Command::Save(Box::new(data))).ok();
+    }
+
+    // Write any pending state now and wait until it is on disk
+    pub fn flush(&self) {
+        flush(&self.sender);
+    }
+
+    // Flush and exit on SIGINT/SIGTERM, so that a killed session doesn't lose edits
+    pub fn flush_on_signal(&self) {
+        let sender = self.sender.clone();
+        let result = ctrlc::set_handler(move || {
+            flush(&sender);
+            std::process::exit(0);
+        });
+
+        if let Err(err) = result {
+            eprintln!("Failed to install signal handler: {}", err);
+        }
+    }
+}
+
+impl Drop for Autosaver {
+    fn drop(&mut self) {
+        self.sender.send(Command::Stop).ok();
+        if let Some(worker) = self.worker.take() {
+            worker.join().ok();
+        }
+    }
+}
+
+fn flush(sender: &mpsc::Sender<Command>) {
+    let (done_sender, done) = mpsc::channel();
+    if sender.send(Command::Flush(done_sender)).is_ok() {
+        done.recv().ok();
+    }
+}
+
+fn write(path: &str, pending: &mut Option<(Box<PersistentData>, Instant)>) {
+    if let Some((data, _)) = pending.take() {
+        if let Err(err) = storage::save(path, &data) {
+            eprintln!("Failed to save {}: {}", path, err);
+        }
+    }
+}
+
+fn run(path: String, receiver: mpsc::Receiver<Command>) {
+    // Latest unsaved state and when it first became dirty
+    let mut pending: Option<(Box<PersistentData>, Instant)> = None;
+
+    loop {
+        let dirty_since = pending.as_ref().map(|(_, dirty_since)| { *dirty_since });
+        let command = match dirty_since {
+            Some(dirty_since) => {
+                let deadline = (Instant::now() + DEBOUNCE).min(dirty_since + MAX_DELAY);
+                match receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                    Ok(command) => command,
+                    Err(mpsc::RecvTimeoutError::Timeout) => {
+                        write(&path, &mut pending);
+                        continue;
+                    }
+                    Err(mpsc::RecvTimeoutError::Disconnected) => Command::Stop,
+                }
+            }
+            None => receiver.recv().unwrap_or(Command::Stop),
+        };
+
+        match command {
+            Command::Save(data) => {
+                pending = Some((data, dirty_since.unwrap_or_else(Instant::now)));
+            }
+
+            Command::Flush(done) => {
+                write(&path, &mut pending);
+                done.send(()).ok();
+            }
+
+            Command::Stop => {
+                write(&path, &mut pending);
+                break;
+            }
+        }
+    }
+}
@@ -0,0 +1,840 @@
+// Two-way synchronization of the timetable with a CalDAV calendar collection. Every
+// filled slot is a weekly event with the UID of ical::slot_uid, named after its
+// activity. Edits of such events in the calendar are applied to the plan, and weekly
+// events created there are adopted: placed in the plan like an import, then replaced
+// by events of the app.
+//
+// The content of each slot at the last synchronization is kept with the schedule, so
+// a change on one side can be told apart from the other side being outdated. When both
+// sides changed a slot, `conflicts` picks the winner and the conflict is reported.
+//
+// Any server with PROPFIND will do. To try it against Radicale:
+//
+//     python3 -m radicale --storage-filesystem-folder=/tmp/radicale --auth-type=none
+//
+// then create a calendar in its web interface at http://localhost:5232 and use its
+// URL, such as http://localhost:5232/user/<calendar>/, with any user name.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use url::Url;
+
+use crate::clock;
+use crate::ical;
+use crate::import::{self, ImportedEvent};
+use crate::storage::{Activity, PersistentData};
+use crate::CAPTIONS;
+
+pub const DEFAULT_MINUTES: u32 = 15;
+pub const MAX_MINUTES: u32 = 240;
+
+const TIMEOUT: Duration = Duration::from_secs(30);
+
+const PROPFIND_ETAGS: &str = "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+<d:propfind xmlns:d=\"DAV:\"><d:prop><d:getetag/></d:prop></d:propfind>";
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Savefile, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Winner {
+    #[default]
+    Local,
+    Remote,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Savefile, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct CalDavSettings {
+    // Synchronize every `minutes`. Synchronizing by hand works regardless.
+    #[savefile_versions = "14.."]
+    pub enabled: bool,
+
+    // Calendar collection, such as https://example.com/dav/calendars/me/timetable/
+    #[savefile_versions = "14.."]
+    pub url: String,
+    #[savefile_versions = "14.."]
+    pub username: String,
+
+    // Kept in binary schedule files as it is, which only the user can read, so an app
+    // password is best here. It is never written to .json and .toml files or served
+    // by the API, so schedules in those formats need it entered again after each start.
+    #[savefile_versions = "14.."]
+    #[serde(skip_serializing)]
+    pub password: String,
+
+    #[savefile_versions = "14.."]
+    pub minutes: u32,
+
+    // Side which is kept when both changed a slot
+    #[savefile_versions = "14.."]
+    pub conflicts: Winner,
+}
+
+impl Default for CalDavSettings {
+    fn default() -> CalDavSettings {
+        CalDavSettings {
+            enabled: false,
+            url: String::new(),
+            username: String::new(),
+            password: String::new(),
+            minutes: DEFAULT_MINUTES,
+            conflicts: Winner::Local,
+        }
+    }
+}
+
+// A slot as both sides had it after the last synchronization
+#[derive(Debug, Clone, Default, Eq, PartialEq, Savefile, serde::Serialize, serde::Deserialize)]
+pub struct SyncedSlot {
+    #[savefile_versions = "14.."]
+    pub day: usize,
+    #[savefile_versions = "14.."]
+    pub block: usize,
+
+    // Path of the event on the server, and its ETag
+    #[savefile_versions = "14.."]
+    pub href: String,
+    #[savefile_versions = "14.."]
+    pub etag: String,
+
+    #[savefile_versions = "14.."]
+    pub summary: String,
+    #[savefile_versions = "14.."]
+    pub url: String,
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct Report {
+    // Events created or changed on the server
+    pub uploaded: usize,
+    // Events removed from the server, including outdated copies
+    pub deleted: usize,
+    // Slots changed to match the calendar
+    pub applied: usize,
+    // Events created in the calendar and taken into the plan
+    pub adopted: usize,
+    // Slots changed on both sides, with what was kept
+    pub conflicts: Vec<String>,
+    // Events which don't fit the plan, left alone
+    pub skipped: Vec<String>,
+}
+
+impl Report {
+    pub fn changed_plan(&self) -> bool {
+        self.applied > 0
+    }
+
+    // "2 uploaded, 1 from the calendar, 1 conflict"
+    pub fn summary(&self) -> String {
+        let counts = [
+            (self.uploaded, "uploaded"),
+            (self.deleted, "deleted"),
+            (self.applied, "from the calendar"),
+            (self.adopted, "adopted"),
+        ];
+
+        let mut parts: Vec<String> = counts.iter()
+            .filter(|(count, _)| { *count > 0 })
+            .map(|(count, label)| { format!("{} {}", count, label) })
+            .collect();
+
+        match self.conflicts.len() {
+            0 => {}
+            1 => parts.push(String::from("1 conflict")),
+            count => parts.push(format!("{} conflicts", count)),
+        }
+
+        if parts.is_empty() {
+            String::from("Up to date")
+        } else {
+            parts.join(", ")
+        }
+    }
+}
+
+// (day, block)
+type Slot = (usize, usize);
+
+// What a slot holds, as far as the calendar can tell
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct Content {
+    summary: String,
+    url: String,
+}
+
+impl Content {
+    fn describe(content: Option<&Content>) -> &str {
+        content.map(|content| { content.summary.as_str() }).unwrap_or("empty")
+    }
+}
+
+fn same(a: Option<&Content>, b: Option<&Content>) -> bool {
+    match (a, b) {
+        (Some(a), Some(b)) => import::same_name(&a.summary, &b.summary) && a.url.trim() == b.url.trim(),
+        (None, None) => true,
+        _ => false,
+    }
+}
+
+// Event of a slot on the server
+struct Resource {
+    href: String,
+    etag: String,
+    content: Content,
+}
+
+enum RemoteEvent {
+    // Event of the app which still fits its slot
+    Slot(usize, usize, Content),
+    // Any other weekly event, and whether the app created it
+    Other(Vec<ImportedEvent>, bool),
+    // Not a weekly event, with the reason
+    Unusable(String),
+}
+
+fn in_plan(data: &PersistentData, day: usize, block: usize) -> bool {
+    data.slots.get(day).map(|slots| { block < slots.len() }).unwrap_or(false)
+}
+
+fn classify(data: &PersistentData, text: &str) -> RemoteEvent {
+    let (events, skipped) = match ical::parse(text) {
+        Ok(parsed) => parsed,
+        Err(err) => return RemoteEvent::Unusable(err),
+    };
+
+    if events.is_empty() {
+        let reason = skipped.into_iter().next().unwrap_or_else(|| { String::from("Empty calendar object") });
+        return RemoteEvent::Unusable(reason);
+    }
+
+    let slot = ical::event_uid(text).and_then(|uid| { ical::parse_slot_uid(&uid) });
+    if let (Some((day, block)), [event]) = (slot, events.as_slice()) {
+        let fits = event.day == day && import::match_block(&data.block_times, event.start) == Some(block);
+        if fits && in_plan(data, day, block) {
+            return RemoteEvent::Slot(day, block, Content { summary: event.summary.clone(), url: event.url.clone() });
+        }
+    }
+
+    RemoteEvent::Other(events, slot.is_some())
+}
+
+// Slots of the events, or why one of them doesn't fit the plan
+fn place(data: &PersistentData, events: &[ImportedEvent]) -> Result<Vec<(Slot, Content)>, String> {
+    events.iter().map(|event| {
+        let block = import::match_block(&data.block_times, event.start)
+            .filter(|block| { in_plan(data, event.day, *block) })
+            .ok_or_else(|| { format!("{}: no block at this time", event.describe()) })?;
+
+        Ok(((event.day, block), Content { summary: event.summary.clone(), url: event.url.clone() }))
+    }).collect()
+}
+
+fn local_content(data: &PersistentData, day: usize, block: usize) -> Option<Content> {
+    let id = (*data.slots.get(day)?.get(block)?)?;
+    let activity = data.activities.iter().find(|activity| { activity.id == id })?;
+    Some(Content { summary: activity.name.clone(), url: activity.url.clone() })
+}
+
+// Fill the slot as the calendar has it. The link is a property of the activity, so
+// editing it in one event changes it for all of them.
+fn apply(data: &mut PersistentData, day: usize, block: usize, content: Option<&Content>) {
+    let id = content.map(|content| {
+        let existing = data.activities.iter_mut()
+            .find(|activity| { import::same_name(&activity.name, &content.summary) });
+
+        match existing {
+            Some(activity) => {
+                activity.url = content.url.trim().to_string();
+                activity.id
+            }
+            None => {
+                let id = data.next_activity_id;
+                data.activities.push(Activity {
+                    name: content.summary.trim().to_string(),
+                    url: content.url.trim().to_string(),
+                    id,
+                    ..Activity::default()
+                });
+                data.next_activity_id += 1;
+                id
+            }
+        }
+    });
+
+    data.slots[day][block] = id;
+}
+
+fn synced(data: &PersistentData, day: usize, block: usize) -> Option<&SyncedSlot> {
+    data.caldav_synced.iter().find(|slot| { slot.day == day && slot.block == block })
+}
+
+fn record(data: &mut PersistentData, day: usize, block: usize, state: Option<(String, String, Content)>) {
+    data.caldav_synced.retain(|slot| { slot.day != day || slot.block != block });
+    if let Some((href, etag, content)) = state {
+        data.caldav_synced.push(SyncedSlot { day, block, href, etag, summary: content.summary, url: content.url });
+    }
+}
+
+// Inner text of the elements with the given local name, whatever their namespace prefix
+fn xml_elements<'a>(xml: &'a str, name: &str) -> Vec<&'a str> {
+    let mut found = vec![];
+    let mut rest = xml;
+    while let Some(open) = rest.find('<') {
+        let after = &rest[open + 1..];
+        let tag_end = match after.find('>') {
+            Some(tag_end) => tag_end,
+            None => break,
+        };
+
+        let tag = &after[..tag_end];
+        rest = &after[tag_end + 1..];
+
+        let tag_name = tag.split(|c: char| { c.is_whitespace() || c == '/' }).next().unwrap_or("");
+        let local_name = tag_name.rsplit(':').next().unwrap_or(tag_name);
+        if tag.starts_with('/') || tag.ends_with('/') || local_name != name {
+            continue;
+        }
+
+        let close = format!("</{}>", tag_name);
+        if let Some(end) = rest.find(&close) {
+            found.push(&rest[..end]);
+            rest = &rest[end + close.len()..];
+        }
+    }
+
+    found
+}
+
+fn xml_unescape(text: &str) -> String {
+    text.replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&amp;", "&")
+}
+
+struct Client<'a> {
+    settings: &'a CalDavSettings,
+    // The collection, always ending with a slash so that event paths resolve inside it
+    base: Url,
+}
+
+impl<'a> Client<'a> {
+    fn new(settings: &'a CalDavSettings) -> Result<Client<'a>, String> {
+        let mut url = settings.url.trim().to_string();
+        if url.is_empty() {
+            return Err(String::from("No CalDAV calendar URL is set"));
+        }
+        if !url.ends_with('/') {
+            url.push('/');
+        }
+
+        let base = Url::parse(&url).map_err(|err| { format!("Invalid calendar URL '{}': {}", url, err) })?;
+        Ok(Client { settings, base })
+    }
+
+    fn request(&self, method: &str, href: &str) -> Result<ureq::Request, String> {
+        let url = self.base.join(href).map_err(|err| { format!("Invalid event path '{}': {}", href, err) })?;
+        let mut request = ureq::request(method, url.as_str());
+        request.timeout(TIMEOUT);
+        if !self.settings.username.is_empty() {
+            request.auth(&self.settings.username, &self.settings.password);
+        }
+
+        Ok(request)
+    }
+
+    // Path for the event of a slot which isn't on the server yet
+    fn new_href(&self, day: usize, block: usize) -> String {
+        self.base.join(&format!("timeplan-{}-{}.ics", day, block))
+            .map(|url| { url.path().to_string() })
+            .unwrap_or_default()
+    }
+
+    // Paths and ETags of all events in the collection
+    fn list(&self) -> Result<Vec<(String, String)>, String> {
+        let mut request = self.request("PROPFIND", "")?;
+        request.set("Depth", "1").set("Content-Type", "application/xml; charset=utf-8");
+        let body = send(&mut request, Some(PROPFIND_ETAGS), false)?
+            .into_string()
+            .map_err(|err| { format!("Failed to read the calendar: {}", err) })?;
+
+        Ok(xml_elements(&body, "response").into_iter().filter_map(|response| {
+            let href = xml_unescape(xml_elements(response, "href").first()?.trim());
+            let etag = xml_unescape(xml_elements(response, "getetag").first()?.trim());
+            // The collection itself is listed too
+            if href.ends_with('/') || etag.is_empty() {
+                return None;
+            }
+
+            Some((href, etag))
+        }).collect())
+    }
+
+    fn get(&self, href: &str) -> Result<String, String> {
+        send(&mut self.request("GET", href)?, None, false)?
+            .into_string()
+            .map_err(|err| { format!("Failed to read {}: {}", href, err) })
+    }
+
+    // Create the event, or replace it if it still has the given ETag. Returns the new
+    // ETag, which not every server reports.
+    fn put(&self, href: &str, etag: Option<&str>, calendar: &str) -> Result<String, String> {
+        let mut request = self.request("PUT", href)?;
+        request.set("Content-Type", "text/calendar; charset=utf-8");
+        match etag {
+            Some(etag) => request.set("If-Match", etag),
+            None => request.set("If-None-Match", "*"),
+        };
+
+        let response = send(&mut request, Some(calendar), false)?;
+        Ok(response.header("ETag").unwrap_or("").to_string())
+    }
+
+    fn delete(&self, href: &str, etag: &str) -> Result<(), String> {
+        let mut request = self.request("DELETE", href)?;
+        request.set("If-Match", etag);
+        send(&mut request, None, true)?;
+        Ok(())
+    }
+}
+
+// The response, or what went wrong. A 412 means the event changed on the server
+// while synchronizing, and the next synchronization will pick that up.
+fn send(request: &mut ureq::Request, body: Option<&str>, missing_ok: bool) -> Result<ureq::Response, String> {
+    let response = match body {
+        Some(body) => request.send_string(body),
+        None => request.call(),
+    };
+
+    let action = format!("{} {}", request.get_method(), request.get_url());
+    if let Some(err) = response.synthetic_error() {
+        return Err(format!("{}: {}", action, err));
+    }
+    if response.error() && !(missing_ok && response.status() == 404) {
+        return Err(format!("{}: {} {}", action, response.status(), response.status_text()));
+    }
+
+    Ok(response)
+}
+
+// The calendar as it was read, before anything is changed
+#[derive(Default)]
+struct Calendar {
+    // Events of the app which fit their slots
+    slots: HashMap<Slot, Resource>,
+    // Adopted and moved events, by the slots they belong in
+    incoming: HashMap<Slot, Content>,
+    // Adopted events and outdated copies as (href, ETag), which make way for the
+    // events of their slots
+    obsolete: Vec<(String, String)>,
+}
+
+impl Calendar {
+    fn add(&mut self, data: &PersistentData, href: String, etag: String, event: RemoteEvent,
+           report: &mut Report) {
+        match event {
+            RemoteEvent::Slot(day, block, content) if !self.slots.contains_key(&(day, block)) => {
+                self.slots.insert((day, block), Resource { href, etag, content });
+            }
+            RemoteEvent::Slot(_, _, _) => self.obsolete.push((href, etag)),
+            RemoteEvent::Other(events, ours) => match place(data, &events) {
+                Ok(placed) if !placed.is_empty() => {
+                    self.incoming.extend(placed);
+                    self.obsolete.push((href, etag));
+                    if !ours {
+                        report.adopted += 1;
+                    }
+                }
+                // Events of the app which were moved out of the plan, or whose slot is gone,
+                // are in the way of the events for their slots
+                Err(reason) if ours => {
+                    report.skipped.push(format!("{}, removed", reason));
+                    self.obsolete.push((href, etag));
+                }
+                _ if ours => self.obsolete.push((href, etag)),
+                Ok(_) => {}
+                Err(reason) => report.skipped.push(reason),
+            },
+            RemoteEvent::Unusable(reason) => report.skipped.push(reason),
+        }
+    }
+
+    fn content(&self, slot: &Slot) -> Option<Content> {
+        self.incoming.get(slot).cloned()
+            .or_else(|| { self.slots.get(slot).map(|resource| { resource.content.clone() }) })
+    }
+}
+
+// Events which are unchanged since the last synchronization, so they needn't be read
+fn known(data: &PersistentData, href: &str, etag: &str) -> Option<RemoteEvent> {
+    let slot = data.caldav_synced.iter().find(|slot| { slot.href == href && slot.etag == etag })?;
+    if !in_plan(data, slot.day, slot.block) {
+        return Some(RemoteEvent::Other(vec![], true));
+    }
+
+    let content = Content { summary: slot.summary.clone(), url: slot.url.clone() };
+    Some(RemoteEvent::Slot(slot.day, slot.block, content))
+}
+
+fn plan_slots(data: &PersistentData) -> Vec<Slot> {
+    data.slots.iter().enumerate()
+        .flat_map(|(day, slots)| { (0..slots.len()).map(move |block| { (day, block) }) })
+        .collect()
+}
+
+// Decide every slot against what both sides had after the last synchronization, and
+// fill the slots where the calendar wins
+fn merge(data: &mut PersistentData, calendar: &Calendar, winner: Winner, report: &mut Report) {
+    for (day, block) in plan_slots(data) {
+        let base = synced(data, day, block).map(|slot| {
+            Content { summary: slot.summary.clone(), url: slot.url.clone() }
+        });
+        let local = local_content(data, day, block);
+        let theirs = calendar.content(&(day, block));
+
+        let local_changed = !same(local.as_ref(), base.as_ref());
+        let remote_changed = !same(theirs.as_ref(), base.as_ref());
+        let take_remote = match (local_changed, remote_changed) {
+            (_, false) => false,
+            (false, true) => true,
+            (true, true) if same(local.as_ref(), theirs.as_ref()) => false,
+            (true, true) => {
+                let kept = match winner {
+                    Winner::Local => "kept this one",
+                    Winner::Remote => "kept the calendar's",
+                };
+                report.conflicts.push(format!("{} {}: {} here, {} in the calendar, {}",
+                                              CAPTIONS.get(day).unwrap_or(&"?"), data.block_times[block],
+                                              Content::describe(local.as_ref()),
+                                              Content::describe(theirs.as_ref()), kept));
+                winner == Winner::Remote
+            }
+        };
+
+        if take_remote {
+            apply(data, day, block, theirs.as_ref());
+            report.applied += 1;
+        }
+    }
+}
+
+// What a slot needs on the server to match the plan
+#[derive(Debug, Eq, PartialEq)]
+enum Upload {
+    // Empty on both sides
+    Nothing,
+    // Remove the event with this path and ETag
+    Delete(String, String),
+    // The event is up to date
+    Keep(String, String, Content),
+    // Create the event at this path, or replace it if it has the ETag
+    Put(String, Option<String>, Content),
+}
+
+// The calendar gets what the plan has after merging, which may differ from the decision
+// for slots of an activity whose link was changed in another slot. Events which aren't
+// on the server yet go to `new_href`.
+fn uploads(data: &PersistentData, calendar: &Calendar,
+           new_href: impl Fn(usize, usize) -> String) -> Vec<(Slot, Upload)> {
+    plan_slots(data).into_iter().map(|(day, block)| {
+        let upload = match (local_content(data, day, block), calendar.slots.get(&(day, block))) {
+            (None, None) => Upload::Nothing,
+            (None, Some(resource)) => Upload::Delete(resource.href.clone(), resource.etag.clone()),
+            (Some(content), Some(resource)) if same(Some(&content), Some(&resource.content)) => {
+                Upload::Keep(resource.href.clone(), resource.etag.clone(), content)
+            }
+            (Some(content), Some(resource)) => {
+                Upload::Put(resource.href.clone(), Some(resource.etag.clone()), content)
+            }
+            (Some(content), None) => Upload::Put(new_href(day, block), None, content),
+        };
+
+        ((day, block), upload)
+    }).collect()
+}
+
+// Bring the plan and the calendar in line. Changes are applied to `data` as they are
+// made, so it should be saved even if this fails halfway; the next synchronization
+// carries on from there.
+pub fn sync(data: &mut PersistentData) -> Result<Report, String> {
+    let settings = data.caldav.clone();
+    let client = Client::new(&settings)?;
+    let mut report = Report::default();
+
+    // Read the whole calendar first, so that a failure here changes nothing
+    let mut calendar = Calendar::default();
+    for (href, etag) in client.list()? {
+        let event = match known(data, &href, &etag) {
+            Some(event) => event,
+            None => classify(data, &client.get(&href)?),
+        };
+        calendar.add(data, href, etag, event, &mut report);
+    }
+
+    merge(data, &calendar, settings.conflicts, &mut report);
+
+    for (href, etag) in calendar.obsolete.iter() {
+        client.delete(href, etag)?;
+        report.deleted += 1;
+    }
+
+    let monday = clock::week_start(clock::local_now().date());
+    let stamp = chrono::Utc::now().naive_utc();
+    for ((day, block), upload) in uploads(data, &calendar, |day, block| { client.new_href(day, block) }) {
+        let state = match upload {
+            Upload::Nothing => None,
+            Upload::Delete(href, etag) => {
+                client.delete(&href, &etag)?;
+                report.deleted += 1;
+                None
+            }
+            Upload::Keep(href, etag, content) => Some((href, etag, content)),
+            Upload::Put(href, etag, content) => {
+                let event = ical::export_event(data, day, block, monday, &stamp).unwrap_or_default();
+                let etag = client.put(&href, etag.as_deref(), &ical::wrap_calendar(&[event]))?;
+                report.uploaded += 1;
+                Some((href, etag, content))
+            }
+        };
+
+        record(data, day, block, state);
+    }
+
+    let (days, blocks) = (data.slots.len(), data.block_times.len());
+    data.caldav_synced.retain(|slot| { slot.day < days && slot.block < blocks });
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::BlockTime;
+
+    fn content(summary: &str) -> Content {
+        Content { summary: summary.to_string(), url: String::new() }
+    }
+
+    fn resource(href: &str, summary: &str) -> Resource {
+        Resource { href: href.to_string(), etag: format!("\"{}\"", href), content: content(summary) }
+    }
+
+    // Math and Art in a plan of two days with two blocks, 08:00 and 09:00
+    fn data() -> PersistentData {
+        let mut data = PersistentData::new();
+        data.activities.push(Activity { name: String::from("Math"), id: 0, ..Activity::default() });
+        data.activities.push(Activity { name: String::from("Art"), id: 1, ..Activity::default() });
+        data.next_activity_id = 2;
+        data.block_times = vec![BlockTime { start: 8 * 60, end: 9 * 60 }, BlockTime { start: 9 * 60, end: 10 * 60 }];
+        data.slots = vec![vec![None; 2]; 2];
+        data
+    }
+
+    fn synced_as(data: &mut PersistentData, day: usize, block: usize, summary: &str) {
+        let href = format!("/cal/timeplan-{}-{}.ics", day, block);
+        record(data, day, block, Some((href.clone(), format!("\"{}\"", href), content(summary))));
+    }
+
+    fn name(data: &PersistentData, day: usize, block: usize) -> Option<String> {
+        local_content(data, day, block).map(|content| { content.summary })
+    }
+
+    fn weekly_event(uid: &str, start: &str, summary: &str) -> String {
+        format!("BEGIN:VCALENDAR\r\nVERSION:2.0\r\nBEGIN:VEVENT\r\nUID:{}\r\nSUMMARY:{}\r\n\
+                 DTSTART:{}\r\nRRULE:FREQ=WEEKLY\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n", uid, summary, start)
+    }
+
+    #[test]
+    fn merges_changes_of_either_side() {
+        let mut data = data();
+        let mut calendar = Calendar::default();
+
+        // Changed in the calendar
+        data.slots[0][0] = Some(0);
+        synced_as(&mut data, 0, 0, "Math");
+        calendar.slots.insert((0, 0), resource("a", "Art"));
+        // Changed here
+        data.slots[0][1] = Some(1);
+        synced_as(&mut data, 0, 1, "Math");
+        calendar.slots.insert((0, 1), resource("b", "Math"));
+        // Changed alike on both sides
+        data.slots[1][0] = Some(1);
+        synced_as(&mut data, 1, 0, "Math");
+        calendar.slots.insert((1, 0), resource("c", "art"));
+        // Emptied in the calendar
+        data.slots[1][1] = Some(0);
+        synced_as(&mut data, 1, 1, "Math");
+
+        let mut report = Report::default();
+        merge(&mut data, &calendar, Winner::Local, &mut report);
+        assert_eq!(report.applied, 2);
+        assert!(report.conflicts.is_empty());
+        assert_eq!(data.slots, vec![vec![Some(1), Some(1)], vec![Some(1), None]]);
+        assert_eq!(data.activities.len(), 2);
+    }
+
+    #[test]
+    fn resolves_conflicts_for_the_winner() {
+        for winner in [Winner::Local, Winner::Remote].iter() {
+            let mut data = data();
+            data.slots[0][0] = Some(1);
+            synced_as(&mut data, 0, 0, "Math");
+
+            let mut calendar = Calendar::default();
+            calendar.slots.insert((0, 0), resource("a", "Music"));
+
+            let mut report = Report::default();
+            merge(&mut data, &calendar, *winner, &mut report);
+            assert_eq!(report.conflicts.len(), 1);
+            match winner {
+                Winner::Local => {
+                    assert_eq!(report.applied, 0);
+                    assert_eq!(name(&data, 0, 0).as_deref(), Some("Art"));
+                    assert!(report.conflicts[0].ends_with("Art here, Music in the calendar, kept this one"));
+                }
+                Winner::Remote => {
+                    assert_eq!(report.applied, 1);
+                    assert_eq!(name(&data, 0, 0).as_deref(), Some("Music"));
+                    assert_eq!(data.next_activity_id, 3);
+                    assert!(report.conflicts[0].ends_with("kept the calendar's"));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn adopts_weekly_events_of_the_calendar() {
+        let mut data = data();
+        let mut report = Report::default();
+        let mut calendar = Calendar::default();
+
+        // Tuesday 09:00, not created by the app
+        let foreign = classify(&data, &weekly_event("abc@example.com", "20210202T090000", "Music"));
+        calendar.add(&data, String::from("/cal/abc.ics"), String::from("\"1\""), foreign, &mut report);
+        // Monday 08:00, an event of the app in its slot
+        let own = classify(&data, &weekly_event(&ical::slot_uid(0, 0), "20210201T080000", "Math"));
+        calendar.add(&data, String::from("/cal/own.ics"), String::from("\"2\""), own, &mut report);
+
+        assert_eq!(report.adopted, 1);
+        assert_eq!(calendar.obsolete, vec![(String::from("/cal/abc.ics"), String::from("\"1\""))]);
+        assert_eq!(calendar.incoming.get(&(1, 1)), Some(&content("Music")));
+        assert_eq!(calendar.slots.get(&(0, 0)).map(|resource| { resource.href.as_str() }), Some("/cal/own.ics"));
+
+        merge(&mut data, &calendar, Winner::Local, &mut report);
+        assert_eq!(report.applied, 2);
+        assert_eq!(name(&data, 0, 0).as_deref(), Some("Math"));
+        assert_eq!(name(&data, 1, 1).as_deref(), Some("Music"));
+
+        let uploads = uploads(&data, &calendar, |day, block| { format!("/cal/new-{}-{}.ics", day, block) });
+        assert!(uploads.contains(&((0, 0), Upload::Keep(String::from("/cal/own.ics"), String::from("\"2\""),
+                                                         content("Math")))));
+        assert!(uploads.contains(&((1, 1), Upload::Put(String::from("/cal/new-1-1.ics"), None, content("Music")))));
+    }
+
+    #[test]
+    fn removes_obsolete_events() {
+        let mut data = data();
+        let mut report = Report::default();
+        let mut calendar = Calendar::default();
+        let mut add = |calendar: &mut Calendar, href: &str, event: RemoteEvent| {
+            calendar.add(&data, href.to_string(), String::from("\"1\""), event, &mut report);
+        };
+
+        add(&mut calendar, "/cal/first.ics", RemoteEvent::Slot(0, 0, content("Math")));
+        // A second event for the same slot
+        add(&mut calendar, "/cal/copy.ics", RemoteEvent::Slot(0, 0, content("Math")));
+        // An event of the app on Sunday, which isn't in the plan
+        let sunday = ImportedEvent { day: 6, start: 8 * 60, end: 9 * 60, summary: String::from("Art"), url: String::new() };
+        add(&mut calendar, "/cal/sunday.ics", RemoteEvent::Other(vec![sunday.clone()], true));
+        // A foreign one, which is left alone
+        add(&mut calendar, "/cal/foreign.ics", RemoteEvent::Other(vec![sunday], false));
+        add(&mut calendar, "/cal/todo.ics", RemoteEvent::Unusable(String::from("Not an event")));
+
+        let obsolete: Vec<&str> = calendar.obsolete.iter().map(|(href, _)| { href.as_str() }).collect();
+        assert_eq!(obsolete, vec!["/cal/copy.ics", "/cal/sunday.ics"]);
+        assert_eq!(report.skipped, vec![String::from("Sunday 08:00\u{2013}09:00 Art: no block at this time, removed"),
+                                        String::from("Sunday 08:00\u{2013}09:00 Art: no block at this time"),
+                                        String::from("Not an event")]);
+        assert_eq!(report.adopted, 0);
+
+        // Emptied here since the last synchronization
+        synced_as(&mut data, 0, 0, "Math");
+        merge(&mut data, &calendar, Winner::Local, &mut report);
+        let uploads = uploads(&data, &calendar, |_, _| { unreachable!() });
+        assert_eq!(uploads[0], ((0, 0), Upload::Delete(String::from("/cal/first.ics"), String::from("\"1\""))));
+        assert!(uploads[1..].iter().all(|(_, upload)| { *upload == Upload::Nothing }));
+    }
+
+    #[test]
+    fn recognizes_unchanged_events() {
+        let mut data = data();
+        data.slots[0][1] = Some(0);
+        synced_as(&mut data, 0, 1, "Math");
+        let (href, etag) = (data.caldav_synced[0].href.clone(), data.caldav_synced[0].etag.clone());
+
+        assert!(matches!(known(&data, &href, &etag), Some(RemoteEvent::Slot(0, 1, _))));
+        assert!(known(&data, &href, "\"changed\"").is_none());
+
+        // The block was removed
+        data.slots[0].truncate(1);
+        assert!(matches!(known(&data, &href, &etag), Some(RemoteEvent::Other(events, true)) if events.is_empty()));
+    }
+
+    #[test]
+    fn finds_xml_elements() {
+        let xml = "<?xml version=\"1.0\"?><d:multistatus xmlns:d=\"DAV:\">\
+                   <d:response><d:href>/cal/</d:href><d:propstat><d:prop><d:getetag/></d:prop></d:propstat></d:response>\
+                   <d:response><d:href>/cal/a.ics</d:href><d:propstat><d:prop>\
+                   <d:getetag>&quot;1&quot;</d:getetag></d:prop></d:propstat></d:response>\
+                   <response xmlns=\"DAV:\"><href>/cal/b.ics</href></response>\
+                   </d:multistatus>";
+
+        let responses = xml_elements(xml, "response");
+        assert_eq!(responses.len(), 3);
+        assert!(xml_elements(responses[0], "getetag").is_empty());
+        assert_eq!(xml_elements(responses[1], "href"), vec!["/cal/a.ics"]);
+        assert_eq!(xml_unescape(xml_elements(responses[1], "getetag")[0]), "\"1\"");
+        assert_eq!(xml_elements(responses[2], "href"), vec!["/cal/b.ics"]);
+        assert!(xml_elements(xml, "missing").is_empty());
+        assert!(xml_elements("<d:href>/cal/", "href").is_empty());
+    }
+
+    // Needs an empty calendar collection, such as one of Radicale as described at the top:
+    //
+    //     TIMEPLAN_TEST_CALDAV_URL=http://localhost:5232/user/test/ cargo test -- --ignored
+    #[test]
+    #[ignore]
+    fn syncs_with_a_server() {
+        let url = std::env::var("TIMEPLAN_TEST_CALDAV_URL").expect("TIMEPLAN_TEST_CALDAV_URL is not set");
+        let mut data = data();
+        data.caldav.url = url;
+        data.caldav.username = String::from("timeplan");
+        data.slots[0][0] = Some(0);
+        data.slots[1][1] = Some(1);
+
+        let report = sync(&mut data).unwrap();
+        assert_eq!((report.uploaded, report.deleted, report.applied), (2, 0, 0));
+        assert_eq!(sync(&mut data).unwrap(), Report::default());
+
+        // Math becomes Music in the calendar, and a foreign event appears on Tuesday 08:00
+        let settings = data.caldav.clone();
+        let client = Client::new(&settings).unwrap();
+        let math = synced(&data, 0, 0).unwrap().clone();
+        let music = weekly_event(&ical::slot_uid(0, 0), "20210201T080000", "Music");
+        client.put(&math.href, Some(&math.etag), &music).unwrap();
+        let foreign = weekly_event("foreign@example.com", "20210202T080000", "Choir");
+        client.put("foreign.ics", None, &foreign).unwrap();
+
+        let report = sync(&mut data).unwrap();
+        assert_eq!((report.applied, report.adopted, report.deleted, report.uploaded), (2, 1, 1, 1));
+        assert_eq!(name(&data, 0, 0).as_deref(), Some("Music"));
+        assert_eq!(name(&data, 1, 0).as_deref(), Some("Choir"));
+        assert_eq!(client.list().unwrap().len(), 3);
+
+        // Leave the calendar empty again
+        data.slots = vec![vec![None; 2]; 2];
+        assert_eq!(sync(&mut data).unwrap().deleted, 3);
+        assert!(client.list().unwrap().is_empty());
+        assert!(data.caldav_synced.is_empty());
+    }
+}
@@ -0,0 +1,218 @@
+use std::path::PathBuf;
+
+use crate::status::StatusFormat;
+
+pub const USAGE: &str = "\
+Usage: timeplan [OPTIONS] [COMMAND]
+
+Commands:
+    convert <input> <output>    Convert a schedule between formats, picked by
+                                extension: .json, .toml or binary otherwise
+    export-ics [<output>]       Export the timetable as iCalendar, to stdout
+                                if no output is given
+    export-csv [<output>]       Export the timetable as day,start,end,activity,url
+                                rows, to stdout if no output is given
+    now                         Print the block running now
+    next                        Print the next planned block
+    join                        Open the meeting of the block running now
+    list-activities             Print the ID, name and URL of every activity
+    status [--format <format>] [--follow]
+                                Print the current and next activity for a
+                                status bar, as waybar JSON, i3blocks or plain
+                                text. --follow prints a new line at each block
+                                boundary, and every minute while a countdown
+                                of less than an hour is shown
+    add-activity --name <name> [--url <url>]
+                                Add an activity and print its ID
+    assign <day> <block> <activity>
+                                Put an activity, given by name or ID, in a slot.
+                                Blocks are numbered from 1 or given by their
+                                start time, and 'none' empties the slot
+    sync                        Synchronize the timetable with the CalDAV
+                                calendar set up in the window
+
+Options:
+    --config <path>    Schedule file to use, also read from $TIMEPLAN_CONFIG
+    --daemon           Run reminders, automatic joins and CalDAV sync without
+                       the window, reloading the schedule when its file changes
+    -h, --help         Print this help
+
+Only one window or daemon runs per schedule. Starting another one, or running
+join, sync, add-activity or assign, hands the command to the running one.";
+
+#[derive(Debug)]
+pub enum Command {
+    // Convert (input, output)
+    Convert(PathBuf, PathBuf),
+
+    // Export to the given file, or stdout
+    ExportIcs(Option<PathBuf>),
+
+    // Export to the given file, or stdout
+    ExportCsv(Option<PathBuf>),
+
+    Now,
+    Next,
+    Join,
+    ListActivities,
+
+    AddActivity {
+        name: String,
+        url: String,
+    },
+
+    // Assign (day, block, activity) as given on the command line
+    Assign(String, String, String),
+
+    Status {
+        format: StatusFormat,
+        follow: bool,
+    },
+
+    Sync,
+}
+
+#[derive(Debug, Default)]
+pub struct Options {
+    pub config: Option<PathBuf>,
+    pub help: bool,
+    pub daemon: bool,
+    pub command: Option<Command>,
+}
+
+// Global options may appear anywhere, everything else belongs to the command
+pub fn parse(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    let mut rest = vec![];
+
+    while let Some(arg) = args.next() {
+        if arg == "--config" {
+            let value = args.next()
+                .ok_or_else(|| { String::from("--config requires a path") })?;
+            options.config = Some(PathBuf::from(value));
+        } else if let Some(value) = arg.strip_prefix("--config=") {
+            options.config = Some(PathBuf::from(value));
+        } else if arg == "--daemon" {
+            options.daemon = true;
+        } else if arg == "-h" || arg == "--help" {
+            options.help = true;
+        } else {
+            rest.push(arg);
+        }
+    }
+
+    options.command = parse_command(&rest)?;
+    if options.daemon && options.command.is_some() {
+        return Err(String::from("--daemon can't be combined with a command"));
+    }
+
+    Ok(options)
+}
+
+fn parse_command(args: &[String]) -> Result<Option<Command>, String> {
+    let (name, args) = match args.split_first() {
+        Some(split) => split,
+        None => return Ok(None),
+    };
+
+    let command = match (name.as_str(), args) {
+        ("convert", [input, output]) => {
+            Command::Convert(PathBuf::from(input), PathBuf::from(output))
+        }
+        ("convert", _) => {
+            return Err(String::from("convert requires an input and an output path"));
+        }
+
+        ("export-ics", []) => Command::ExportIcs(None),
+        ("export-ics", [output]) if output == "-" => Command::ExportIcs(None),
+        ("export-ics", [output]) => Command::ExportIcs(Some(PathBuf::from(output))),
+        ("export-ics", _) => {
+            return Err(String::from("export-ics takes at most one output path"));
+        }
+
+        ("export-csv", []) => Command::ExportCsv(None),
+        ("export-csv", [output]) if output == "-" => Command::ExportCsv(None),
+        ("export-csv", [output]) => Command::ExportCsv(Some(PathBuf::from(output))),
+        ("export-csv", _) => {
+            return Err(String::from("export-csv takes at most one output path"));
+        }
+
+        ("now", []) => Command::Now,
+        ("next", []) => Command::Next,
+        ("join", []) => Command::Join,
+        ("list-activities", []) => Command::ListActivities,
+        ("sync", []) => Command::Sync,
+        ("now", _) | ("next", _) | ("join", _) | ("list-activities", _) | ("sync", _) => {
+            return Err(format!("{} takes no arguments", name));
+        }
+
+        ("add-activity", args) => parse_add_activity(args)?,
+        ("status", args) => parse_status(args)?,
+
+        ("assign", [day, block, activity]) => {
+            Command::Assign(day.clone(), block.clone(), activity.clone())
+        }
+        ("assign", _) => {
+            return Err(String::from("assign requires a day, a block and an activity"));
+        }
+
+        _ => {
+            return Err(format!("Unknown argument '{}'", name));
+        }
+    };
+
+    Ok(Some(command))
+}
+
+fn parse_status(args: &[String]) -> Result<Command, String> {
+    let mut format = StatusFormat::Plain;
+    let mut follow = false;
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let name = if arg == "--format" {
+            args.next().ok_or_else(|| { String::from("--format requires a value") })?.as_str()
+        } else if let Some(value) = arg.strip_prefix("--format=") {
+            value
+        } else if arg == "--follow" {
+            follow = true;
+            continue;
+        } else {
+            return Err(format!("Unknown argument '{}' to status", arg));
+        };
+
+        format = StatusFormat::parse(name)
+            .ok_or_else(|| { format!("Unknown format '{}', use waybar, i3blocks or plain", name) })?;
+    }
+
+    Ok(Command::Status { format, follow })
+}
+
+fn parse_add_activity(args: &[String]) -> Result<Command, String> {
+    let mut name = None;
+    let mut url = String::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |option: &str| {
+            args.next().cloned().ok_or_else(|| { format!("{} requires a value", option) })
+        };
+
+        if arg == "--name" {
+            name = Some(value("--name")?);
+        } else if arg == "--url" {
+            url = value("--url")?;
+        } else if let Some(value) = arg.strip_prefix("--name=") {
+            name = Some(value.to_string());
+        } else if let Some(value) = arg.strip_prefix("--url=") {
+            url = value.to_string();
+        } else {
+            return Err(format!("Unknown argument '{}' to add-activity", arg));
+        }
+    }
+
+    match name {
+        Some(name) if !name.trim().is_empty() => Ok(Command::AddActivity { name, url }),
+        _ => Err(String::from("add-activity requires --name")),
+    }
+}
@@ -0,0 +1,101 @@
+// Where the current time falls in the weekly plan, and which blocks come next.
+// Times are local wall clock time, like the block times of the plan.
+
+use chrono::{Datelike, NaiveDate, NaiveDateTime, Timelike};
+
+use crate::storage::{BlockTime, PersistentData};
+
+// One block of the plan on a concrete date
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Occurrence {
+    pub day: usize,
+    pub block: usize,
+    // None for empty slots
+    pub activity: Option<usize>,
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+}
+
+pub fn local_now() -> NaiveDateTime {
+    chrono::Local::now().naive_local()
+}
+
+pub fn week_start(date: NaiveDate) -> NaiveDate {
+    date - chrono::Duration::days(date.weekday().num_days_from_monday() as i64)
+}
+
+// Occurrence of the block in the week starting at the given Monday
+pub fn block_occurrence(monday: NaiveDate, day: usize, time: &BlockTime) -> (NaiveDateTime, NaiveDateTime) {
+    let midnight = (monday + chrono::Duration::days(day as i64)).and_hms_opt(0, 0, 0).unwrap();
+    (midnight + chrono::Duration::minutes(time.start as i64),
+     midnight + chrono::Duration::minutes(time.end as i64))
+}
+
+// (day of the plan, 0 = Monday, minutes since midnight)
+pub fn plan_position(now: &NaiveDateTime) -> (usize, u32) {
+    (now.weekday().num_days_from_monday() as usize, now.hour() * 60 + now.minute())
+}
+
+fn slot(data: &PersistentData, day: usize, block: usize) -> Option<usize> {
+    data.slots.get(day)?.get(block).cloned().flatten()
+}
+
+// Block running at the given time, filled or not
+pub fn current(data: &PersistentData, now: &NaiveDateTime) -> Option<Occurrence> {
+    let (day, minutes) = plan_position(now);
+    if day >= data.slots.len() {
+        return None;
+    }
+
+    let block = data.block_times.iter().position(|time| { time.start <= minutes && minutes < time.end })?;
+    let (start, end) = block_occurrence(week_start(now.date()), day, &data.block_times[block]);
+    Some(Occurrence { day, block, activity: slot(data, day, block), start, end })
+}
+
+// Next start of every filled slot after the given time, soonest first
+pub fn upcoming(data: &PersistentData, now: &NaiveDateTime) -> Vec<Occurrence> {
+    let monday = week_start(now.date());
+    let mut occurrences = vec![];
+
+    for (day, slots) in data.slots.iter().enumerate() {
+        for (block, activity) in slots.iter().enumerate() {
+            let (activity, time) = match (activity, data.block_times.get(block)) {
+                (Some(activity), Some(time)) => (*activity, time),
+                _ => continue,
+            };
+
+            let (mut start, mut end) = block_occurrence(monday, day, time);
+            if start <= *now {
+                start += chrono::Duration::weeks(1);
+                end += chrono::Duration::weeks(1);
+            }
+
+            occurrences.push(Occurrence { day, block, activity: Some(activity), start, end });
+        }
+    }
+
+    occurrences.sort_by_key(|occurrence| { occurrence.start });
+    occurrences
+}
+
+pub fn next(data: &PersistentData, now: &NaiveDateTime) -> Option<Occurrence> {
+    upcoming(data, now).into_iter().next()
+}
+
+// Whole minutes until the given time, rounded up
+pub fn minutes_until(now: &NaiveDateTime, time: &NaiveDateTime) -> i64 {
+    let seconds = (*time - *now).num_seconds();
+    (seconds + 59).div_euclid(60)
+}
+
+// "in 12 min", "at 14:00" later today, or "on Tuesday at 08:00"
+pub fn describe_start(now: &NaiveDateTime, start: &NaiveDateTime) -> String {
+    let minutes = minutes_until(now, start);
+    if minutes < 60 {
+        format!("in {} min", minutes.max(0))
+    } else if start.date() == now.date() {
+        format!("at {}", start.format("%H:%M"))
+    } else {
+        format!("on {} at {}", start.format("%A"), start.format("%H:%M"))
+    }
+}
@@ -0,0 +1,326 @@
+// Commands which run without the GUI
+
+use std::io::Write;
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+
+use crate::caldav;
+use crate::cli::Command;
+use crate::clock::{self, Occurrence};
+use crate::csv_format;
+use crate::paths;
+use crate::ical;
+use crate::import;
+use crate::launcher;
+use crate::status;
+use crate::storage::{self, Activity, PersistentData};
+use crate::CAPTIONS;
+
+fn load_schedule(schedule: Option<&Path>) -> Result<PersistentData, String> {
+    let path = schedule.ok_or_else(paths::missing_schedule_error)?;
+    if !path.exists() {
+        return Err(format!("No schedule at {}", path.display()));
+    }
+
+    storage::load(&path.to_string_lossy())
+        .map_err(|err| { format!("Failed to load {}: {}", path.display(), err) })
+}
+
+// Print the status whenever its text changes, reloading the schedule when its file does.
+// Besides the block boundaries, that is every minute while the countdown is in minutes.
+fn follow_status(schedule: Option<&Path>, format: status::StatusFormat) -> Result<(), String> {
+    let path = schedule.ok_or_else(paths::missing_schedule_error)?;
+    let mut data = load_schedule(schedule)?;
+    let mut last_modified = storage::modified(path);
+    let mut last_output = None;
+
+    loop {
+        if storage::modified(path) != last_modified {
+            last_modified = storage::modified(path);
+            // Keep the old schedule if the file is being written
+            if let Ok(new_data) = load_schedule(schedule) {
+                data = new_data;
+            }
+        }
+
+        let output = status::render(&status::status(&data, &clock::local_now()), format, true);
+        if last_output.as_ref() != Some(&output) {
+            writeln!(std::io::stdout(), "{}", output)
+                .map_err(|err| { format!("Failed to write the status: {}", err) })?;
+            last_output = Some(output);
+        }
+
+        thread::sleep(Duration::from_secs(1));
+    }
+}
+
+fn save_schedule(schedule: Option<&Path>, data: &PersistentData) -> Result<(), String> {
+    let path = schedule.ok_or_else(paths::missing_schedule_error)?;
+    storage::save(&path.to_string_lossy(), data)
+        .map_err(|err| { format!("Failed to save {}: {}", path.display(), err) })
+}
+
+fn find_activity(data: &PersistentData, id: Option<usize>) -> Option<&Activity> {
+    let id = id?;
+    data.activities.iter().find(|activity| { activity.id == id })
+}
+
+// "10:00–11:30 https://..."
+fn describe(activity: &Activity, occurrence: &Occurrence) -> String {
+    let mut text = format!("{}\u{2013}{}", occurrence.start.format("%H:%M"), occurrence.end.format("%H:%M"));
+    if !activity.url.is_empty() {
+        text = format!("{} {}", text, activity.url);
+    }
+
+    text
+}
+
+// Number from 1, or the start time of the block
+fn parse_block(data: &PersistentData, text: &str) -> Result<usize, String> {
+    let block = match (text.parse::<usize>(), storage::parse_clock(text)) {
+        (Ok(number), _) => number.checked_sub(1),
+        (_, Some(start)) => import::match_block(&data.block_times, start),
+        _ => return Err(format!("Invalid block '{}', use its number or start time", text)),
+    };
+
+    block.filter(|block| { *block < data.block_times.len() })
+        .ok_or_else(|| { format!("The plan has no block {}", text) })
+}
+
+// Name, then ID
+fn parse_activity(data: &PersistentData, text: &str) -> Result<Option<usize>, String> {
+    if text == "none" {
+        return Ok(None);
+    }
+
+    data.activities.iter()
+        .find(|activity| { import::same_name(&activity.name, text) })
+        .or_else(|| {
+            let id = text.parse::<usize>().ok();
+            data.activities.iter().find(|activity| { Some(activity.id) == id })
+        })
+        .map(|activity| { Some(activity.id) })
+        .ok_or_else(|| { format!("No activity named '{}'", text) })
+}
+
+// Activity of the block running now
+pub fn current_activity(data: &PersistentData) -> Result<&Activity, String> {
+    clock::current(data, &clock::local_now())
+        .and_then(|current| { find_activity(data, current.activity) })
+        .ok_or_else(|| { String::from("Nothing is planned now") })
+}
+
+pub fn check_new_activity(data: &PersistentData, name: &str) -> Result<(), String> {
+    if data.activities.iter().any(|activity| { import::same_name(&activity.name, name) }) {
+        return Err(format!("There already is an activity named '{}'", name.trim()));
+    }
+
+    Ok(())
+}
+
+// Returns the ID of the new activity
+pub fn add_activity(data: &mut PersistentData, name: &str, url: &str) -> Result<usize, String> {
+    check_new_activity(data, name)?;
+
+    let id = data.next_activity_id;
+    data.activities.push(Activity {
+        name: name.trim().to_string(),
+        url: url.trim().to_string(),
+        id,
+        ..Activity::default()
+    });
+    data.next_activity_id += 1;
+    Ok(id)
+}
+
+// (day, block, activity) of an assignment given on the command line
+pub fn resolve_assignment(data: &PersistentData, day: &str, block: &str,
+                          activity: &str) -> Result<(usize, usize, Option<usize>), String> {
+    let day_idx = csv_format::parse_day(day)
+        .filter(|day| { *day < data.slots.len() })
+        .ok_or_else(|| { format!("The plan has no day '{}'", day) })?;
+
+    Ok((day_idx, parse_block(data, block)?, parse_activity(data, activity)?))
+}
+
+// "Monday 10:00–11:30: Math"
+pub fn describe_assignment(data: &PersistentData, day: usize, block: usize, activity: Option<usize>) -> String {
+    let name = find_activity(data, activity).map(|activity| { activity.name.as_str() });
+    format!("{} {}: {}", CAPTIONS[day], data.block_times[block], name.unwrap_or("empty"))
+}
+
+pub fn run(command: Command, schedule: Option<&Path>) -> Result<(), String> {
+    match command {
+        Command::Convert(input, output) => {
+            storage::convert(&input.to_string_lossy(), &output.to_string_lossy())
+                .map_err(|err| {
+                    format!("Failed to convert {} to {}: {}", input.display(), output.display(), err)
+                })
+        }
+
+        Command::ExportIcs(output) => {
+            let data = load_schedule(schedule)?;
+            match output {
+                Some(path) => {
+                    ical::export_to_file(&data, &path)
+                        .map_err(|err| { format!("Failed to write {}: {}", path.display(), err) })
+                }
+                None => {
+                    print!("{}", ical::export(&data));
+                    Ok(())
+                }
+            }
+        }
+
+        Command::ExportCsv(output) => {
+            let data = load_schedule(schedule)?;
+            match output {
+                Some(path) => {
+                    csv_format::export_to_file(&data, &path)
+                        .map_err(|err| { format!("Failed to write {}: {}", path.display(), err) })
+                }
+                None => {
+                    csv_format::write(&data, std::io::stdout())
+                        .map_err(|err| { format!("Failed to write CSV: {}", err) })
+                }
+            }
+        }
+
+        Command::Now => {
+            let data = load_schedule(schedule)?;
+            let now = clock::local_now();
+            match clock::current(&data, &now) {
+                Some(current) => match find_activity(&data, current.activity) {
+                    Some(activity) => println!("{} {}", activity.name, describe(activity, &current)),
+                    None => println!("Nothing planned until {}", current.end.format("%H:%M")),
+                },
+                None => println!("Nothing planned now"),
+            }
+
+            Ok(())
+        }
+
+        Command::Next => {
+            let data = load_schedule(schedule)?;
+            let now = clock::local_now();
+            let next = clock::next(&data, &now)
+                .and_then(|next| { find_activity(&data, next.activity).map(|activity| { (activity, next) }) });
+
+            match next {
+                Some((activity, next)) => {
+                    println!("{} {}, {}", activity.name, clock::describe_start(&now, &next.start),
+                             describe(activity, &next));
+                }
+                None => println!("Nothing planned"),
+            }
+
+            Ok(())
+        }
+
+        Command::Join => {
+            let data = load_schedule(schedule)?;
+            launcher::launch_activity(&data.launcher, current_activity(&data)?)
+        }
+
+        Command::ListActivities => {
+            let data = load_schedule(schedule)?;
+            for activity in data.activities.iter() {
+                println!("{}\t{}\t{}", activity.id, activity.name, activity.url);
+            }
+
+            Ok(())
+        }
+
+        Command::AddActivity { name, url } => {
+            let mut data = load_schedule(schedule)?;
+            let id = add_activity(&mut data, &name, &url)?;
+            save_schedule(schedule, &data)?;
+            println!("{}", id);
+            Ok(())
+        }
+
+        Command::Status { format, follow: false } => {
+            let data = load_schedule(schedule)?;
+            println!("{}", status::render(&status::status(&data, &clock::local_now()), format, false));
+            Ok(())
+        }
+
+        Command::Status { format, follow: true } => follow_status(schedule, format),
+
+        Command::Sync => {
+            let mut data = load_schedule(schedule)?;
+            let result = caldav::sync(&mut data);
+            // Whatever was synchronized before a failure is kept
+            save_schedule(schedule, &data)?;
+
+            let report = result?;
+            println!("{}", report.summary());
+            for conflict in report.conflicts.iter() {
+                println!("Conflict: {}", conflict);
+            }
+            for skipped in report.skipped.iter() {
+                println!("Skipped: {}", skipped);
+            }
+
+            Ok(())
+        }
+
+        Command::Assign(day, block, activity) => {
+            let mut data = load_schedule(schedule)?;
+            let (day, block, activity) = resolve_assignment(&data, &day, &block, &activity)?;
+
+            data.slots[day][block] = activity;
+            save_schedule(schedule, &data)?;
+
+            println!("{}", describe_assignment(&data, day, block, activity));
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::BlockTime;
+
+    // Monday to Friday, with blocks at 08:00 and 10:00
+    fn data() -> PersistentData {
+        let mut data = PersistentData::new();
+        data.activities.push(Activity { name: String::from("Math"), id: 0, ..Activity::default() });
+        data.activities.push(Activity { name: String::from("Art"), id: 3, ..Activity::default() });
+        data.next_activity_id = 4;
+        data.block_times = vec![
+            BlockTime { start: 8 * 60, end: 9 * 60 + 30 },
+            BlockTime { start: 10 * 60, end: 11 * 60 + 30 },
+        ];
+        data.slots = vec![vec![None; 2]; 5];
+        data
+    }
+
+    fn resolve(day: &str, block: &str, activity: &str) -> Result<(usize, usize, Option<usize>), String> {
+        resolve_assignment(&data(), day, block, activity)
+    }
+
+    #[test]
+    fn resolves_assignments() {
+        assert_eq!(resolve("mon", "1", "Math"), Ok((0, 0, Some(0))));
+        assert_eq!(resolve("Friday", "10:00", " art "), Ok((4, 1, Some(3))));
+        assert_eq!(resolve("2", "08:45", "3"), Ok((1, 0, Some(3))));
+        assert_eq!(resolve("we", "2", "none"), Ok((2, 1, None)));
+        assert_eq!(describe_assignment(&data(), 4, 1, Some(3)), "Friday 10:00\u{2013}11:30: Art");
+    }
+
+    #[test]
+    fn refuses_what_isnt_in_the_plan() {
+        assert_eq!(resolve("sat", "1", "Math"), Err(String::from("The plan has no day 'sat'")));
+        assert_eq!(resolve("8", "1", "Math"), Err(String::from("The plan has no day '8'")));
+        assert_eq!(resolve("mon", "3", "Math"), Err(String::from("The plan has no block 3")));
+        assert_eq!(resolve("mon", "0", "Math"), Err(String::from("The plan has no block 0")));
+        assert_eq!(resolve("mon", "12:00", "Math"), Err(String::from("The plan has no block 12:00")));
+        assert_eq!(resolve("mon", "first", "Math"),
+                   Err(String::from("Invalid block 'first', use its number or start time")));
+        assert_eq!(resolve("mon", "1", "Music"), Err(String::from("No activity named 'Music'")));
+        assert_eq!(resolve("mon", "1", "1"), Err(String::from("No activity named '1'")));
+    }
+}
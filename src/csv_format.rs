@@ -0,0 +1,324 @@
+// CSV export of the timetable as day,start,end,activity,url rows, and import of
+// spreadsheets whose columns are mapped to those fields by the user.
+
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::import::ImportedEvent;
+use crate::storage::{self, PersistentData};
+use crate::CAPTIONS;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum CsvField {
+    Day,
+    Start,
+    End,
+    Activity,
+    Url,
+}
+
+impl CsvField {
+    pub const ALL: [CsvField; 5] = [CsvField::Day, CsvField::Start, CsvField::End,
+                                    CsvField::Activity, CsvField::Url];
+
+    pub fn label(self) -> &'static str {
+        match self {
+            CsvField::Day => "Day",
+            CsvField::Start => "Start",
+            CsvField::End => "End",
+            CsvField::Activity => "Activity",
+            CsvField::Url => "URL",
+        }
+    }
+
+    // Header names which are mapped to the field without asking
+    fn aliases(self) -> &'static [&'static str] {
+        match self {
+            CsvField::Day => &["day", "weekday", "day of week"],
+            CsvField::Start => &["start", "from", "begin", "time", "start time"],
+            CsvField::End => &["end", "to", "until", "finish", "end time"],
+            CsvField::Activity => &["activity", "subject", "class", "course", "name", "summary", "title"],
+            CsvField::Url => &["url", "link", "meeting", "location"],
+        }
+    }
+
+    fn index(self) -> usize {
+        CsvField::ALL.iter().position(|field| { *field == self }).unwrap()
+    }
+}
+
+// Column used for each field, if any
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ColumnMapping {
+    columns: [Option<usize>; 5],
+}
+
+impl ColumnMapping {
+    pub fn get(&self, field: CsvField) -> Option<usize> {
+        self.columns[field.index()]
+    }
+
+    pub fn set(&mut self, field: CsvField, column: Option<usize>) {
+        self.columns[field.index()] = column;
+    }
+
+    pub fn guess(headers: &[String]) -> ColumnMapping {
+        let mut mapping = ColumnMapping::default();
+        for field in CsvField::ALL.iter() {
+            let column = headers.iter().position(|header| {
+                field.aliases().iter().any(|alias| { header.trim().eq_ignore_ascii_case(alias) })
+            });
+            mapping.set(*field, column);
+        }
+
+        mapping
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CsvTable {
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+}
+
+pub fn read_table(path: &Path) -> Result<CsvTable, String> {
+    let error = |err: csv::Error| { format!("{}: {}", path.display(), err) };
+
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_path(path)
+        .map_err(error)?;
+
+    let headers = reader.headers().map_err(error)?.iter().map(String::from).collect();
+    let rows = reader.records()
+        .map(|record| { record.map(|record| { record.iter().map(String::from).collect() }) })
+        .collect::<Result<Vec<Vec<String>>, csv::Error>>()
+        .map_err(error)?;
+
+    Ok(CsvTable { headers, rows })
+}
+
+// Weekday names, abbreviated to at least two letters, or 1 to 7 starting on Monday
+pub fn parse_day(text: &str) -> Option<usize> {
+    let text = text.trim().trim_end_matches('.').to_lowercase();
+    if let Ok(number) = text.parse::<usize>() {
+        return if (1..=CAPTIONS.len()).contains(&number) { Some(number - 1) } else { None };
+    }
+
+    if text.len() < 2 {
+        return None;
+    }
+
+    CAPTIONS.iter().position(|caption| { caption.to_lowercase().starts_with(&text) })
+}
+
+// Start and end times, from a range such as "09:00-10:30" when there is no end column.
+// None if the end comes before the start.
+fn parse_times(start: &str, end: Option<&str>) -> Option<(u32, u32)> {
+    let (start, end) = match end {
+        Some(end) => (start, end),
+        None => {
+            let mut parts = start.splitn(2, |c| { c == '-' || c == '\u{2013}' });
+            (parts.next()?, parts.next().unwrap_or(start))
+        }
+    };
+
+    let start = storage::parse_clock(start)?;
+    let end = storage::parse_clock(end)?;
+    if end < start {
+        return None;
+    }
+
+    Some((start, end))
+}
+
+#[derive(Debug, Default)]
+pub struct CsvImport {
+    pub events: Vec<ImportedEvent>,
+    // (name, url) of rows without a day, which only add the activity
+    pub activities: Vec<(String, String)>,
+    pub skipped: Vec<String>,
+}
+
+pub fn to_events(table: &CsvTable, mapping: &ColumnMapping) -> Result<CsvImport, String> {
+    if mapping.get(CsvField::Activity).is_none() {
+        return Err(String::from("Choose the column with the activity name"));
+    }
+
+    let mut import = CsvImport::default();
+    for (idx, row) in table.rows.iter().enumerate() {
+        let cell = |field: CsvField| {
+            mapping.get(field)
+                .and_then(|column| { row.get(column) })
+                .map(|value| { value.as_str() })
+                .filter(|value| { !value.is_empty() })
+        };
+
+        // Counting the header, as spreadsheets do
+        let line = idx + 2;
+        let summary = match cell(CsvField::Activity) {
+            Some(summary) => summary.to_string(),
+            None => {
+                if row.iter().any(|value| { !value.is_empty() }) {
+                    import.skipped.push(format!("Row {}: no activity", line));
+                }
+                continue;
+            }
+        };
+
+        let url = cell(CsvField::Url).map(String::from).unwrap_or_default();
+        let (day, start) = match (cell(CsvField::Day), cell(CsvField::Start)) {
+            (None, None) => {
+                import.activities.push((summary, url));
+                continue;
+            }
+            (Some(day), Some(start)) => (day, start),
+            (None, Some(_)) => {
+                import.skipped.push(format!("Row {}: {} has no day", line, summary));
+                continue;
+            }
+            (Some(_), None) => {
+                import.skipped.push(format!("Row {}: {} has no start time", line, summary));
+                continue;
+            }
+        };
+
+        let day = match parse_day(day) {
+            Some(day) => day,
+            None => {
+                import.skipped.push(format!("Row {}: unknown day '{}'", line, day));
+                continue;
+            }
+        };
+
+        let (start, end) = match parse_times(start, cell(CsvField::End)) {
+            Some(times) => times,
+            None => {
+                import.skipped.push(format!("Row {}: invalid time for {}", line, summary));
+                continue;
+            }
+        };
+
+        import.events.push(ImportedEvent { day, start, end, summary, url });
+    }
+
+    Ok(import)
+}
+
+// Filled slots, followed by activities which aren't in the plan
+pub fn write<W: io::Write>(data: &PersistentData, writer: W) -> csv::Result<()> {
+    let mut writer = csv::Writer::from_writer(writer);
+    writer.write_record(["day", "start", "end", "activity", "url"])?;
+
+    let mut planned = vec![];
+    for (day, slots) in data.slots.iter().enumerate() {
+        for (block, id) in slots.iter().enumerate() {
+            let activity = id.and_then(|id| { data.activities.iter().find(|activity| { activity.id == id }) });
+            if let (Some(activity), Some(time)) = (activity, data.block_times.get(block)) {
+                writer.write_record([CAPTIONS[day].to_string(),
+                                     storage::format_clock(time.start),
+                                     storage::format_clock(time.end),
+                                     activity.name.clone(),
+                                     activity.url.clone()])?;
+                planned.push(activity.id);
+            }
+        }
+    }
+
+    for activity in data.activities.iter().filter(|activity| { !planned.contains(&activity.id) }) {
+        writer.write_record(["", "", "", &activity.name, &activity.url])?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn export_to_file(data: &PersistentData, path: &Path) -> csv::Result<()> {
+    if let Some(dir) = path.parent() {
+        if dir != Path::new("") {
+            fs::create_dir_all(dir)?;
+        }
+    }
+
+    write(data, fs::File::create(path)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strings(values: &[&str]) -> Vec<String> {
+        values.iter().map(|value| { value.to_string() }).collect()
+    }
+
+    #[test]
+    fn guesses_columns_from_aliases() {
+        let mapping = ColumnMapping::guess(&strings(&["Weekday", " From ", "TO", "Subject", "Link", "Room"]));
+        assert_eq!(mapping.get(CsvField::Day), Some(0));
+        assert_eq!(mapping.get(CsvField::Start), Some(1));
+        assert_eq!(mapping.get(CsvField::End), Some(2));
+        assert_eq!(mapping.get(CsvField::Activity), Some(3));
+        assert_eq!(mapping.get(CsvField::Url), Some(4));
+
+        let mapping = ColumnMapping::guess(&strings(&["Teacher", "Room"]));
+        assert_eq!(mapping, ColumnMapping::default());
+    }
+
+    #[test]
+    fn parses_days() {
+        assert_eq!(parse_day("Monday"), Some(0));
+        assert_eq!(parse_day(" tu "), Some(1));
+        assert_eq!(parse_day("Wed."), Some(2));
+        assert_eq!(parse_day("7"), Some(6));
+        assert_eq!(parse_day("0"), None);
+        assert_eq!(parse_day("8"), None);
+        assert_eq!(parse_day("t"), None);
+        assert_eq!(parse_day("Someday"), None);
+    }
+
+    #[test]
+    fn parses_times() {
+        assert_eq!(parse_times("09:00", Some("10:30")), Some((9 * 60, 10 * 60 + 30)));
+        assert_eq!(parse_times("09:00-10:30", None), Some((9 * 60, 10 * 60 + 30)));
+        assert_eq!(parse_times("09:00\u{2013}10:30", None), Some((9 * 60, 10 * 60 + 30)));
+        assert_eq!(parse_times("09:00", None), Some((9 * 60, 9 * 60)));
+        assert_eq!(parse_times("10:30", Some("09:00")), None);
+        assert_eq!(parse_times("9", Some("10:00")), None);
+    }
+
+    #[test]
+    fn converts_rows_to_events() {
+        let table = CsvTable {
+            headers: strings(&["day", "time", "class", "url"]),
+            rows: vec![
+                strings(&["Mon", "08:00-09:30", "Math", "https://example.com/math"]),
+                strings(&["", "", "Art", ""]),
+                strings(&["Fri", "", "Music", ""]),
+                strings(&["Funday", "08:00", "Music", ""]),
+                strings(&["Tue", "10:00-09:00", "Physics", ""]),
+                strings(&["Wed", "12:00", "", ""]),
+                strings(&["", "", "", ""]),
+            ],
+        };
+
+        let import = to_events(&table, &ColumnMapping::guess(&table.headers)).unwrap();
+        assert_eq!(import.events, vec![ImportedEvent {
+            day: 0,
+            start: 8 * 60,
+            end: 9 * 60 + 30,
+            summary: String::from("Math"),
+            url: String::from("https://example.com/math"),
+        }]);
+        assert_eq!(import.activities, vec![(String::from("Art"), String::new())]);
+        assert_eq!(import.skipped, strings(&[
+            "Row 4: Music has no start time",
+            "Row 5: unknown day 'Funday'",
+            "Row 6: invalid time for Physics",
+            "Row 7: no activity",
+        ]));
+
+        assert!(to_events(&table, &ColumnMapping::default()).is_err());
+    }
+}
@@ -0,0 +1,263 @@
+// Headless mode: reminders, automatic joins, CalDAV sync and the read-only API without
+// the window. The schedule is reloaded when its file changes, and every action is
+// logged to stderr.
+
+use std::path::Path;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::api::{self, ApiSettings};
+use crate::autojoin::AutoJoiner;
+use crate::caldav;
+use crate::clock;
+use crate::commands;
+use crate::instance::{Instance, Request};
+use crate::launcher;
+use crate::notification::{DesktopNotifier, Notifier};
+use crate::reminder::{self, Answer, Reminders};
+use crate::storage::{self, PersistentData};
+use crate::trigger::Trigger;
+
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+fn log(message: &str) {
+    eprintln!("[{}] {}", clock::local_now().format("%Y-%m-%d %H:%M:%S"), message);
+}
+
+// A missing file is an empty schedule, like in the window
+fn load(path: &Path) -> Result<PersistentData, String> {
+    if !path.exists() {
+        return Ok(PersistentData::new());
+    }
+
+    storage::load(&path.to_string_lossy()).map_err(|err| { err.to_string() })
+}
+
+fn join(data: &PersistentData, id: usize) {
+    let activity = match data.activities.iter().find(|activity| { activity.id == id }) {
+        Some(activity) => activity,
+        None => return,
+    };
+
+    log(&format!("Joining {}", activity.name));
+    if let Err(err) = launcher::launch_activity(&data.launcher, activity) {
+        log(&err);
+    }
+}
+
+// A failure to listen is logged, the reminders work without the API
+fn serve(settings: &ApiSettings, snapshot: &Arc<Mutex<PersistentData>>) -> Option<api::Server> {
+    if !settings.enabled {
+        return None;
+    }
+
+    match api::start(settings.port, snapshot.clone(), None) {
+        Ok(server) => {
+            log(&format!("Serving the API on http://127.0.0.1:{}", settings.port));
+            Some(server)
+        }
+        Err(err) => {
+            log(&err);
+            None
+        }
+    }
+}
+
+// A copy of the schedule after synchronizing it, and the file's modification time
+// when it was copied
+struct SyncOutcome {
+    modified: Option<SystemTime>,
+    data: PersistentData,
+    result: Result<caldav::Report, String>,
+}
+
+// Synchronize a copy with the CalDAV calendar, so that the API, later launches and
+// the clock aren't kept waiting on the server
+fn sync_copy(path: &Path, snapshot: &Mutex<PersistentData>) -> SyncOutcome {
+    let modified = storage::modified(path);
+    let mut data = snapshot.lock().unwrap().clone();
+    let result = caldav::sync(&mut data)
+        .map_err(|err| { format!("Failed to synchronize with the calendar: {}", err) });
+
+    SyncOutcome { modified, data, result }
+}
+
+// Save and take the synchronized copy, even a partial one, or None if the file changed
+// in the meantime. The calendar may be ahead of the file then, which the next
+// synchronization sorts out.
+fn finish_sync(path: &Path, snapshot: &Mutex<PersistentData>,
+               outcome: SyncOutcome) -> Option<Result<caldav::Report, String>> {
+    let mut current = snapshot.lock().unwrap();
+    if storage::modified(path) != outcome.modified {
+        return None;
+    }
+
+    let saved = save(path, &outcome.data);
+    *current = outcome.data;
+    Some(saved.and(outcome.result))
+}
+
+fn save(path: &Path, data: &PersistentData) -> Result<(), String> {
+    storage::save(&path.to_string_lossy(), data).map_err(|err| { format!("Failed to save {}: {}", path.display(), err) })
+}
+
+// Requests of later launches, which are handled here as there is no window
+fn answer(path: &Path, snapshot: &Mutex<PersistentData>, request: Request) -> Result<String, String> {
+    match request {
+        Request::Show => {
+            Err(String::from("timeplan is running as a daemon for this schedule, stop it to open the window"))
+        }
+        Request::JoinNow => {
+            let data = snapshot.lock().unwrap();
+            let activity = commands::current_activity(&data)?;
+            log(&format!("Joining {}", activity.name));
+            launcher::launch_activity(&data.launcher, activity)?;
+            Ok(String::new())
+        }
+        Request::Sync => {
+            let report = finish_sync(path, snapshot, sync_copy(path, snapshot))
+                .unwrap_or_else(|| { Err(String::from("The schedule changed while synchronizing, try again")) })?;
+            log(&format!("Synchronized with the calendar: {}", report.summary()));
+            Ok(report.summary())
+        }
+        Request::AddActivity(name, url) => {
+            let mut data = snapshot.lock().unwrap();
+            let id = commands::add_activity(&mut data, &name, &url)?;
+            save(path, &data)?;
+            log(&format!("Added {}", name.trim()));
+            Ok(id.to_string())
+        }
+        Request::Assign(day, block, activity) => {
+            let mut data = snapshot.lock().unwrap();
+            let (day, block, activity) = commands::resolve_assignment(&data, &day, &block, &activity)?;
+            data.slots[day][block] = activity;
+            save(path, &data)?;
+
+            let assignment = commands::describe_assignment(&data, day, block, activity);
+            log(&assignment);
+            Ok(assignment)
+        }
+    }
+}
+
+pub fn run(path: &Path, mut instance: Option<Instance>) -> Result<(), String> {
+    let data = load(path).map_err(|err| { format!("Failed to load {}: {}", path.display(), err) })?;
+    let mut last_modified = storage::modified(path);
+    log(&format!("Running {} with {} activities", path.display(), data.activities.len()));
+
+    // Shared with the API, which answers from whatever was loaded last
+    let mut api_settings = data.api;
+    let snapshot = Arc::new(Mutex::new(data));
+    let mut server = serve(&api_settings, &snapshot);
+
+    if let Some(instance) = instance.as_mut() {
+        let snapshot = snapshot.clone();
+        let path = path.to_path_buf();
+        instance.serve(move |request| { answer(&path, &snapshot, request) });
+    }
+
+    let mut autojoiner = AutoJoiner::new();
+    let mut reminders = Reminders::new();
+    let notifier = DesktopNotifier::new();
+    let (answers_tx, answers_rx) = mpsc::channel::<(Trigger, Answer)>();
+    let (syncs_tx, syncs_rx) = mpsc::channel::<SyncOutcome>();
+    let mut last_sync: Option<Instant> = None;
+    let mut syncing = false;
+
+    loop {
+        // A failed reload keeps the previous schedule, the file may be written again soon
+        if storage::modified(path) != last_modified {
+            last_modified = storage::modified(path);
+            match load(path) {
+                Ok(new_data) => {
+                    let new_api = new_data.api;
+                    *snapshot.lock().unwrap() = new_data;
+                    log(&format!("Reloaded {}", path.display()));
+
+                    if new_api != api_settings {
+                        api_settings = new_api;
+                        // The old server has to let go of its port first
+                        server.take();
+                        server = serve(&api_settings, &snapshot);
+                    }
+                }
+                Err(err) => log(&format!("Failed to reload {}: {}", path.display(), err)),
+            }
+        }
+
+        if let Ok(outcome) = syncs_rx.try_recv() {
+            syncing = false;
+            match finish_sync(path, &snapshot, outcome) {
+                Some(result) => {
+                    match result {
+                        Ok(report) => {
+                            log(&format!("Synchronized with the calendar: {}", report.summary()));
+                            for conflict in report.conflicts.iter() {
+                                log(&format!("Conflict: {}", conflict));
+                            }
+                        }
+                        Err(err) => log(&err),
+                    }
+                    // Not a change to reload
+                    last_modified = storage::modified(path);
+                }
+                // Reloaded first on the next round, then synchronized again
+                None => last_sync = None,
+            }
+        }
+
+        // On another thread, as the requests can take long enough for the clock to
+        // miss the start of blocks
+        let (enabled, minutes) = {
+            let data = snapshot.lock().unwrap();
+            (data.caldav.enabled, data.caldav.minutes)
+        };
+        let interval = Duration::from_secs(u64::from(minutes) * 60);
+        if enabled && !syncing && last_sync.map(|at| { at.elapsed() >= interval }).unwrap_or(true) {
+            last_sync = Some(Instant::now());
+            syncing = true;
+
+            let snapshot = snapshot.clone();
+            let path = path.to_path_buf();
+            let syncs = syncs_tx.clone();
+            thread::spawn(move || { syncs.send(sync_copy(&path, &snapshot)).ok(); });
+        }
+
+        let data = snapshot.lock().unwrap();
+
+        let now = clock::local_now();
+        while let Ok((reminder, answer)) = answers_rx.try_recv() {
+            match answer {
+                Answer::Join => join(&data, reminder.activity),
+                Answer::Snooze => {
+                    log("Reminder snoozed");
+                    reminders.snooze(&reminder, &now);
+                }
+                Answer::Dismiss => {}
+            }
+        }
+
+        for planned in autojoiner.due(&data, &now) {
+            join(&data, planned.activity);
+        }
+
+        for reminder in reminders.due(&data, &now) {
+            let (title, body) = reminder::describe(&data, &reminder, &now);
+            log(&format!("Reminder: {}, {}", title, body));
+
+            let answers = answers_tx.clone();
+            let expires = reminder::expiry(&reminder, &now);
+            let result = notifier.remind(&title, &body, expires, Box::new(move |answer| {
+                answers.send((reminder, answer)).ok();
+            }));
+
+            if let Err(err) = result {
+                log(&err);
+            }
+        }
+
+        drop(data);
+        thread::sleep(CHECK_INTERVAL);
+    }
+}
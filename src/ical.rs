@@ -0,0 +1,539 @@
+// RFC 5545 export and import of the timetable. Times are written as floating local
+// time, so calendar clients show the blocks at the same wall clock time as the app.
+
+use chrono::{Datelike, NaiveDate, NaiveDateTime, TimeZone, Timelike};
+use chrono_tz::Tz;
+use std::fs;
+use std::path::Path;
+
+use crate::clock::{self, block_occurrence, week_start};
+use crate::import::ImportedEvent;
+use crate::storage::{PersistentData, MINUTES_PER_DAY};
+
+const PRODID: &str = "-//timeplan//timeplan//EN";
+
+static BYDAY: &[&str] = &["MO", "TU", "WE", "TH", "FR", "SA", "SU"];
+
+// Escape a TEXT value
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            ';' => escaped.push_str("\\;"),
+            ',' => escaped.push_str("\\,"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => {}
+            _ => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+// Lines longer than 75 octets are folded, without splitting UTF-8 sequences
+fn push_line(out: &mut String, line: &str) {
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            length = 1;
+        }
+
+        out.push(c);
+        length += c.len_utf8();
+    }
+
+    out.push_str("\r\n");
+}
+
+fn format_date_time(time: &NaiveDateTime) -> String {
+    time.format("%Y%m%dT%H%M%S").to_string()
+}
+
+// Stable per slot, so that clients update the event when the slot changes
+pub fn slot_uid(day: usize, block: usize) -> String {
+    format!("timeplan-{}-{}@timeplan", day, block)
+}
+
+// Inverse of slot_uid
+pub fn parse_slot_uid(uid: &str) -> Option<(usize, usize)> {
+    let mut parts = uid.strip_prefix("timeplan-")?.strip_suffix("@timeplan")?.splitn(2, '-');
+    Some((parts.next()?.parse().ok()?, parts.next()?.parse().ok()?))
+}
+
+// VEVENT for one filled slot, without the surrounding calendar
+pub fn export_event(data: &PersistentData, day: usize, block: usize,
+                    monday: NaiveDate, stamp: &NaiveDateTime) -> Option<String> {
+    let id = (*data.slots.get(day)?.get(block)?)?;
+    let activity = data.activities.iter().find(|activity| { activity.id == id })?;
+    let time = data.block_times.get(block)?;
+    let (start, end) = block_occurrence(monday, day, time);
+
+    let mut out = String::new();
+    push_line(&mut out, "BEGIN:VEVENT");
+    push_line(&mut out, &format!("UID:{}", slot_uid(day, block)));
+    push_line(&mut out, &format!("DTSTAMP:{}Z", format_date_time(stamp)));
+    push_line(&mut out, &format!("DTSTART:{}", format_date_time(&start)));
+    push_line(&mut out, &format!("DTEND:{}", format_date_time(&end)));
+    push_line(&mut out, &format!("RRULE:FREQ=WEEKLY;BYDAY={}", BYDAY[day % BYDAY.len()]));
+    push_line(&mut out, &format!("SUMMARY:{}", escape(&activity.name)));
+    if !activity.url.is_empty() {
+        push_line(&mut out, &format!("URL:{}", activity.url));
+        push_line(&mut out, &format!("LOCATION:{}", escape(&activity.url)));
+    }
+    push_line(&mut out, "END:VEVENT");
+
+    Some(out)
+}
+
+pub fn wrap_calendar(events: &[String]) -> String {
+    let mut out = String::new();
+    push_line(&mut out, "BEGIN:VCALENDAR");
+    push_line(&mut out, "VERSION:2.0");
+    push_line(&mut out, &format!("PRODID:{}", PRODID));
+    push_line(&mut out, "CALSCALE:GREGORIAN");
+    for event in events.iter() {
+        out.push_str(event);
+    }
+    push_line(&mut out, "END:VCALENDAR");
+    out
+}
+
+// One weekly-recurring event per filled block, starting in the current week
+pub fn export(data: &PersistentData) -> String {
+    let monday = week_start(chrono::Local::now().naive_local().date());
+    let stamp = chrono::Utc::now().naive_utc();
+
+    let events: Vec<String> = data.slots.iter().enumerate().flat_map(|(day, slots)| {
+        (0..slots.len()).filter_map(move |block| { export_event(data, day, block, monday, &stamp) })
+    }).collect();
+
+    wrap_calendar(&events)
+}
+
+pub fn export_to_file(data: &PersistentData, path: &Path) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        if dir != Path::new("") {
+            fs::create_dir_all(dir)?;
+        }
+    }
+
+    fs::write(path, export(data))
+}
+
+// Content line split into its parts, parameter names and the name uppercased
+struct Property {
+    name: String,
+    params: Vec<(String, String)>,
+    value: String,
+}
+
+impl Property {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params.iter().find(|(key, _)| { key == name }).map(|(_, value)| { value.as_str() })
+    }
+}
+
+fn unfold(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = vec![];
+    for line in text.split('\n') {
+        let line = line.strip_suffix('\r').unwrap_or(line);
+        match lines.last_mut() {
+            Some(last) if line.starts_with(' ') || line.starts_with('\t') => last.push_str(&line[1..]),
+            _ if line.is_empty() => {}
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    lines
+}
+
+fn parse_property(line: &str) -> Option<Property> {
+    // The value starts at the first colon which isn't inside a quoted parameter
+    let mut quoted = false;
+    let colon = line.char_indices().find(|&(_, c)| {
+        if c == '"' {
+            quoted = !quoted;
+        }
+        c == ':' && !quoted
+    })?.0;
+
+    let mut head = line[..colon].split(';');
+    let name = head.next()?.trim().to_uppercase();
+    let params = head.filter_map(|param| {
+        let mut parts = param.splitn(2, '=');
+        Some((parts.next()?.trim().to_uppercase(), parts.next()?.trim_matches('"').to_string()))
+    }).collect();
+
+    Some(Property { name, params, value: line[colon + 1..].to_string() })
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') | Some('N') => unescaped.push('\n'),
+            Some(other) => unescaped.push(other),
+            None => {}
+        }
+    }
+
+    unescaped.trim().to_string()
+}
+
+// IANA zone of a TZID parameter. The optional leading slash marks a globally unique ID.
+fn time_zone(name: &str) -> Option<Tz> {
+    name.trim().trim_start_matches('/').parse().ok()
+}
+
+// Local wall clock time, converted from UTC or from the zone given by TZID
+fn parse_date_time(property: &Property) -> Option<NaiveDateTime> {
+    let value = property.value.trim();
+    if let Some(utc) = value.strip_suffix('Z') {
+        let time = NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S").ok()?;
+        return Some(chrono::Utc.from_utc_datetime(&time).with_timezone(&chrono::Local).naive_local());
+    }
+
+    let time = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()?;
+    match property.param("TZID") {
+        Some(name) => {
+            let zoned = time_zone(name)?.from_local_datetime(&time).earliest()?;
+            Some(zoned.with_timezone(&chrono::Local).naive_local())
+        }
+        None => Some(time),
+    }
+}
+
+// UNTIL of a rule, a date or a date-time. A date includes the whole day.
+fn parse_until(value: &str) -> Option<NaiveDateTime> {
+    let value = value.trim();
+    match NaiveDate::parse_from_str(value, "%Y%m%d") {
+        Ok(date) => date.and_hms_opt(23, 59, 59),
+        Err(_) => {
+            let property = Property { name: String::from("UNTIL"), params: vec![], value: value.to_string() };
+            parse_date_time(&property)
+        }
+    }
+}
+
+// Length in minutes of a DURATION value such as PT1H30M
+fn parse_duration(value: &str) -> Option<u32> {
+    let value = value.trim().trim_start_matches('+');
+    let mut minutes = 0;
+    let mut number = String::new();
+    for c in value.strip_prefix('P')?.chars() {
+        let unit = match c {
+            '0'..='9' => {
+                number.push(c);
+                continue;
+            }
+            'T' => continue,
+            'W' => 7 * MINUTES_PER_DAY,
+            'D' => MINUTES_PER_DAY,
+            'H' => 60,
+            'M' => 1,
+            'S' => 0,
+            _ => return None,
+        };
+
+        minutes += number.parse::<u32>().ok()? * unit;
+        number.clear();
+    }
+
+    Some(minutes)
+}
+
+fn find_url(text: &str) -> Option<String> {
+    let start = ["https://", "http://"].iter().filter_map(|scheme| { text.find(scheme) }).min()?;
+    let url: String = text[start..].chars()
+        .take_while(|c| { !c.is_whitespace() && !"<>\"".contains(*c) })
+        .collect();
+    Some(url)
+}
+
+// Weekly occurrences of one VEVENT, or the reason it can't be imported
+fn import_event(properties: &[Property], now: &NaiveDateTime) -> Result<Vec<ImportedEvent>, String> {
+    let get = |name: &str| { properties.iter().find(|property| { property.name == name }) };
+
+    let summary = get("SUMMARY").map(|property| { unescape(&property.value) }).unwrap_or_default();
+    if summary.is_empty() {
+        return Err(String::from("Event without a summary"));
+    }
+
+    let label = &summary;
+
+    let start_property = get("DTSTART").ok_or_else(|| { format!("{}: no start time", label) })?;
+    if start_property.param("VALUE") == Some("DATE") {
+        return Err(format!("{}: all-day event", label));
+    }
+
+    if let Some(zone) = start_property.param("TZID").filter(|zone| { time_zone(zone).is_none() }) {
+        return Err(format!("{}: unknown time zone '{}'", label, zone));
+    }
+
+    let start = parse_date_time(start_property)
+        .ok_or_else(|| { format!("{}: invalid start time '{}'", label, start_property.value) })?;
+
+    let length = match (get("DTEND").and_then(parse_date_time), get("DURATION")) {
+        (Some(end), _) => (end - start).num_minutes().max(0) as u32,
+        (None, Some(duration)) => parse_duration(&duration.value)
+            .ok_or_else(|| { format!("{}: invalid duration '{}'", label, duration.value) })?,
+        (None, None) => 0,
+    };
+
+    let rule = get("RRULE").ok_or_else(|| { format!("{}: does not repeat weekly", label) })?;
+    let mut weekdays = vec![];
+    let mut until = None;
+    let mut count = None;
+    for part in rule.value.split(';') {
+        let mut parts = part.splitn(2, '=');
+        match (parts.next().unwrap_or("").to_uppercase().as_str(), parts.next().unwrap_or("")) {
+            ("FREQ", freq) if !freq.eq_ignore_ascii_case("WEEKLY") => {
+                return Err(format!("{}: repeats {}, not weekly", label, freq.to_lowercase()));
+            }
+            ("INTERVAL", interval) if interval.trim() != "1" => {
+                return Err(format!("{}: repeats every {} weeks", label, interval));
+            }
+            ("BYDAY", days) => {
+                for day in days.split(',') {
+                    // Weekly rules may not have ordinals, but tolerate them
+                    let day = day.trim_start_matches(|c: char| { c.is_ascii_digit() || c == '+' || c == '-' });
+                    let weekday = BYDAY.iter().position(|name| { name.eq_ignore_ascii_case(day) })
+                        .ok_or_else(|| { format!("{}: unknown day '{}'", label, day) })?;
+                    weekdays.push(weekday);
+                }
+            }
+            ("UNTIL", value) => {
+                until = Some(parse_until(value).ok_or_else(|| { format!("{}: invalid end '{}'", label, value) })?);
+            }
+            ("COUNT", value) => {
+                count = Some(value.trim().parse::<usize>()
+                             .map_err(|_| { format!("{}: invalid count '{}'", label, value) })?);
+            }
+            _ => {}
+        }
+    }
+
+    if weekdays.is_empty() {
+        weekdays.push(start.weekday().num_days_from_monday() as usize);
+    } else {
+        // BYDAY is given in the zone of DTSTART, so the days move along when the
+        // conversion to local time crosses midnight
+        let written = NaiveDateTime::parse_from_str(start_property.value.trim().trim_end_matches('Z'), "%Y%m%dT%H%M%S")
+            .map_err(|_| { format!("{}: invalid start time '{}'", label, start_property.value) })?;
+        let shift = (start.date() - written.date()).num_days();
+        for weekday in weekdays.iter_mut() {
+            *weekday = (*weekday as i64 + shift).rem_euclid(7) as usize;
+        }
+    }
+
+    // The plan repeats every week, so only series which are still running are imported.
+    // COUNT occurrences take about that many days of the rule, one week each.
+    let last = match (until, count) {
+        (Some(until), _) => Some(until),
+        (None, Some(count)) => {
+            let weeks = count.div_ceil(weekdays.len());
+            Some(start + chrono::Duration::weeks(weeks.max(1) as i64 - 1))
+        }
+        (None, None) => None,
+    };
+
+    if let Some(last) = last.filter(|last| { last < now }) {
+        return Err(format!("{}: the series ended on {}", label, last.format("%Y-%m-%d")));
+    }
+
+    let url = get("URL").map(|property| { property.value.trim().to_string() })
+        .or_else(|| { get("LOCATION").and_then(|property| { find_url(&unescape(&property.value)) }) })
+        .or_else(|| { get("DESCRIPTION").and_then(|property| { find_url(&unescape(&property.value)) }) })
+        .unwrap_or_default();
+
+    let begin = start.hour() * 60 + start.minute();
+    Ok(weekdays.into_iter().map(|day| {
+        ImportedEvent {
+            day,
+            start: begin,
+            end: (begin + length).min(MINUTES_PER_DAY),
+            summary: summary.clone(),
+            url: url.clone(),
+        }
+    }).collect())
+}
+
+// Weekly events of a calendar, and descriptions of the events which were skipped
+pub fn parse(text: &str) -> Result<(Vec<ImportedEvent>, Vec<String>), String> {
+    parse_at(text, &clock::local_now())
+}
+
+// Series which ended before the given time are skipped
+fn parse_at(text: &str, now: &NaiveDateTime) -> Result<(Vec<ImportedEvent>, Vec<String>), String> {
+    let mut events = vec![];
+    let mut skipped = vec![];
+    let mut seen_calendar = false;
+
+    // Properties of the VEVENT being read, and the depth of nested components such as VALARM
+    let mut current: Option<Vec<Property>> = None;
+    let mut nested = 0;
+
+    for line in unfold(text) {
+        let property = match parse_property(&line) {
+            Some(property) => property,
+            None => continue,
+        };
+
+        let component = property.value.trim().to_uppercase();
+        match (property.name.as_str(), current.as_mut()) {
+            ("BEGIN", None) if component == "VCALENDAR" => seen_calendar = true,
+            ("BEGIN", None) if component == "VEVENT" => current = Some(vec![]),
+            ("BEGIN", Some(_)) => nested += 1,
+            ("END", Some(_)) if nested > 0 => nested -= 1,
+            ("END", Some(_)) => {
+                let properties = current.take().unwrap_or_default();
+                let cancelled = properties.iter().any(|property| {
+                    property.name == "STATUS" && property.value.trim().eq_ignore_ascii_case("CANCELLED")
+                });
+
+                // Changed single occurrences of a series don't alter the weekly plan, and
+                // neither do the dates its EXDATE leaves out
+                let exception = properties.iter().any(|property| { property.name == "RECURRENCE-ID" });
+                if cancelled || exception {
+                    continue;
+                }
+
+                match import_event(&properties, now) {
+                    Ok(occurrences) => events.extend(occurrences),
+                    Err(reason) => skipped.push(reason),
+                }
+            }
+            (_, Some(properties)) if nested == 0 => properties.push(property),
+            _ => {}
+        }
+    }
+
+    if !seen_calendar {
+        return Err(String::from("Not an iCalendar file"));
+    }
+
+    Ok((events, skipped))
+}
+
+// UID of the first event, for calendar resources which hold a single event
+pub fn event_uid(text: &str) -> Option<String> {
+    let mut in_event = false;
+    for property in unfold(text).iter().filter_map(|line| { parse_property(line) }) {
+        match property.name.as_str() {
+            "BEGIN" if property.value.trim().eq_ignore_ascii_case("VEVENT") => in_event = true,
+            "UID" if in_event => return Some(property.value.trim().to_string()),
+            _ => {}
+        }
+    }
+
+    None
+}
+
+pub fn parse_file(path: &Path) -> Result<(Vec<ImportedEvent>, Vec<String>), String> {
+    let text = fs::read_to_string(path).map_err(|err| { format!("{}: {}", path.display(), err) })?;
+    parse(&text).map_err(|err| { format!("{}: {}", path.display(), err) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(text: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(text, "%Y%m%dT%H%M%S").unwrap()
+    }
+
+    fn calendar(event: &str) -> String {
+        format!("BEGIN:VCALENDAR\r\nVERSION:2.0\r\nBEGIN:VEVENT\r\nSUMMARY:Math\r\n{}END:VEVENT\r\nEND:VCALENDAR\r\n",
+                event)
+    }
+
+    fn parse_event(event: &str) -> (Vec<ImportedEvent>, Vec<String>) {
+        parse_at(&calendar(event), &time("20210301T000000")).unwrap()
+    }
+
+    #[test]
+    fn parses_slot_uids() {
+        assert_eq!(parse_slot_uid(&slot_uid(4, 12)), Some((4, 12)));
+        assert_eq!(parse_slot_uid("timeplan-0-0@timeplan"), Some((0, 0)));
+        assert_eq!(parse_slot_uid("timeplan-1@timeplan"), None);
+        assert_eq!(parse_slot_uid("timeplan-1-x@timeplan"), None);
+        assert_eq!(parse_slot_uid("timeplan-1-2@example.com"), None);
+        assert_eq!(parse_slot_uid("abc@example.com"), None);
+    }
+
+    #[test]
+    fn converts_tzid_to_local_time() {
+        let (events, skipped) = parse_event("DTSTART;TZID=UTC:20210201T100000\r\n\
+                                             DTEND;TZID=UTC:20210201T113000\r\n\
+                                             RRULE:FREQ=WEEKLY\r\n");
+        assert!(skipped.is_empty());
+
+        let local = chrono::Utc.from_utc_datetime(&time("20210201T100000")).with_timezone(&chrono::Local);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].day, local.weekday().num_days_from_monday() as usize);
+        assert_eq!(events[0].start, local.hour() * 60 + local.minute());
+        assert_eq!(events[0].end - events[0].start, 90);
+
+        // 10:00 in Berlin is 09:00 UTC in winter
+        let (berlin, _) = parse_event("DTSTART;TZID=Europe/Berlin:20210201T100000\r\n\
+                                       RRULE:FREQ=WEEKLY\r\n");
+        let local = chrono::Utc.from_utc_datetime(&time("20210201T090000")).with_timezone(&chrono::Local);
+        assert_eq!(berlin[0].start, local.hour() * 60 + local.minute());
+    }
+
+    #[test]
+    fn shifts_days_with_the_conversion() {
+        // 22:00 in New York is 04:00 on the next day in Berlin
+        let (events, skipped) = parse_event("DTSTART;TZID=America/New_York:20210201T220000\r\n\
+                                             RRULE:FREQ=WEEKLY;BYDAY=MO,SU\r\n");
+        assert!(skipped.is_empty());
+
+        let local = chrono::Utc.from_utc_datetime(&time("20210202T030000")).with_timezone(&chrono::Local);
+        let shift = local.weekday().num_days_from_monday() as usize;
+        assert_eq!(events.iter().map(|event| { event.day }).collect::<Vec<_>>(), vec![shift, (6 + shift) % 7]);
+        assert_eq!(events[0].start, local.hour() * 60 + local.minute());
+    }
+
+    #[test]
+    fn skips_unknown_time_zones() {
+        let (events, skipped) = parse_event("DTSTART;TZID=W. Europe Standard Time:20210201T100000\r\n\
+                                             RRULE:FREQ=WEEKLY\r\n");
+        assert!(events.is_empty());
+        assert_eq!(skipped, vec![String::from("Math: unknown time zone 'W. Europe Standard Time'")]);
+    }
+
+    #[test]
+    fn skips_ended_series() {
+        let (events, skipped) = parse_event("DTSTART:20210104T100000\r\n\
+                                             RRULE:FREQ=WEEKLY;UNTIL=20210201T000000Z\r\n");
+        assert!(events.is_empty());
+        assert_eq!(skipped.len(), 1);
+
+        let (events, skipped) = parse_event("DTSTART:20210104T100000\r\n\
+                                             RRULE:FREQ=WEEKLY;BYDAY=MO,WE;COUNT=6\r\n");
+        assert!(events.is_empty());
+        assert_eq!(skipped, vec![String::from("Math: the series ended on 2021-01-18")]);
+    }
+
+    #[test]
+    fn imports_running_series() {
+        let (events, skipped) = parse_event("DTSTART:20210104T100000\r\n\
+                                             RRULE:FREQ=WEEKLY;UNTIL=20210630\r\n\
+                                             EXDATE:20210111T100000\r\n");
+        assert!(skipped.is_empty());
+        assert_eq!(events.len(), 1);
+
+        let (events, skipped) = parse_event("DTSTART:20210104T100000\r\n\
+                                             RRULE:FREQ=WEEKLY;BYDAY=MO,WE;COUNT=40\r\n");
+        assert!(skipped.is_empty());
+        assert_eq!(events.iter().map(|event| { event.day }).collect::<Vec<_>>(), vec![0, 2]);
+    }
+}
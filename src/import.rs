@@ -0,0 +1,186 @@
+// Shared import pipeline: events read from a calendar or spreadsheet are matched
+// to plan slots and previewed, then applied to the schedule.
+
+use crate::storage::{Activity, BlockTime, PersistentData};
+use crate::CAPTIONS;
+
+// One weekly occurrence, on the given day (0 = Monday) and time in minutes since midnight
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct ImportedEvent {
+    pub day: usize,
+    pub start: u32,
+    pub end: u32,
+    pub summary: String,
+    pub url: String,
+}
+
+impl ImportedEvent {
+    pub fn describe(&self) -> String {
+        let day = CAPTIONS.get(self.day).unwrap_or(&"?");
+        format!("{} {} {}", day, BlockTime { start: self.start, end: self.end }, self.summary)
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct PlannedAssignment {
+    pub day: usize,
+    pub block: usize,
+    pub summary: String,
+    // Name of the different activity already in the slot
+    pub conflict: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct ImportPreview {
+    // (name, url) of activities which don't exist yet
+    pub new_activities: Vec<(String, String)>,
+    pub assignments: Vec<PlannedAssignment>,
+    // Events which couldn't be placed, with the reason
+    pub unmatched: Vec<String>,
+}
+
+impl ImportPreview {
+    pub fn conflicts(&self) -> usize {
+        self.assignments.iter().filter(|assignment| { assignment.conflict.is_some() }).count()
+    }
+
+    // Note an activity to create, unless it exists already. The first non-empty URL wins.
+    pub fn add_activity(&mut self, data: &PersistentData, name: &str, url: &str) {
+        let existing = data.activities.iter().any(|activity| { same_name(&activity.name, name) });
+        match self.new_activities.iter_mut().find(|(new_name, _)| { same_name(new_name, name) }) {
+            Some((_, new_url)) if new_url.is_empty() => {
+                *new_url = url.to_string();
+            }
+            Some(_) => {}
+            None if !existing => {
+                self.new_activities.push((name.trim().to_string(), url.to_string()));
+            }
+            None => {}
+        }
+    }
+}
+
+pub fn same_name(a: &str, b: &str) -> bool {
+    a.trim().to_lowercase() == b.trim().to_lowercase()
+}
+
+// Block starting at the given time, otherwise the block containing it
+pub fn match_block(times: &[BlockTime], start: u32) -> Option<usize> {
+    times.iter().position(|time| { time.start == start })
+        .or_else(|| { times.iter().position(|time| { time.start <= start && start < time.end }) })
+}
+
+pub fn preview(data: &PersistentData, events: &[ImportedEvent], skipped: Vec<String>) -> ImportPreview {
+    let mut preview = ImportPreview { unmatched: skipped, ..ImportPreview::default() };
+
+    for event in events.iter() {
+        if event.day >= data.slots.len() {
+            preview.unmatched.push(format!("{}: the plan has no {}", event.describe(),
+                                           CAPTIONS.get(event.day).unwrap_or(&"such day")));
+            continue;
+        }
+
+        let block = match match_block(&data.block_times, event.start) {
+            Some(block) => block,
+            None => {
+                preview.unmatched.push(format!("{}: no block at this time", event.describe()));
+                continue;
+            }
+        };
+
+        preview.add_activity(data, &event.summary, &event.url);
+
+        let earlier = preview.assignments.iter()
+            .find(|assignment| { assignment.day == event.day && assignment.block == block });
+
+        let conflict = match earlier {
+            Some(earlier) if same_name(&earlier.summary, &event.summary) => continue,
+            Some(earlier) => Some(format!("{} (also imported)", earlier.summary)),
+            None => {
+                let current = data.slots[event.day].get(block).cloned().flatten()
+                    .and_then(|id| { data.activities.iter().find(|activity| { activity.id == id }) });
+
+                match current {
+                    Some(current) if same_name(&current.name, &event.summary) => continue,
+                    Some(current) => Some(current.name.clone()),
+                    None => None,
+                }
+            }
+        };
+
+        preview.assignments.push(PlannedAssignment {
+            day: event.day,
+            block,
+            summary: event.summary.trim().to_string(),
+            conflict,
+        });
+    }
+
+    preview
+}
+
+// Create the new activities and fill the slots, replacing what is there only if asked to.
+// Returns the number of slots written.
+pub fn apply(data: &mut PersistentData, preview: &ImportPreview, replace_conflicts: bool) -> usize {
+    for (name, url) in preview.new_activities.iter() {
+        data.activities.push(Activity {
+            name: name.clone(),
+            url: url.clone(),
+            id: data.next_activity_id,
+            ..Activity::default()
+        });
+        data.next_activity_id += 1;
+    }
+
+    let mut written = 0;
+    for assignment in preview.assignments.iter() {
+        if assignment.conflict.is_some() && !replace_conflicts {
+            continue;
+        }
+
+        let id = data.activities.iter()
+            .find(|activity| { same_name(&activity.name, &assignment.summary) })
+            .map(|activity| { activity.id });
+
+        if let Some(slot) = data.slots.get_mut(assignment.day).and_then(|day| { day.get_mut(assignment.block) }) {
+            *slot = id;
+            written += 1;
+        }
+    }
+
+    written
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event(day: usize, start: u32, summary: &str) -> ImportedEvent {
+        ImportedEvent { day, start, end: start + 60, summary: summary.to_string(), url: String::new() }
+    }
+
+    fn data() -> PersistentData {
+        let mut data = PersistentData::new();
+        data.activities.push(Activity { name: String::from("Math"), id: 0, ..Activity::default() });
+        data.next_activity_id = 1;
+        data.block_times = vec![BlockTime { start: 8 * 60, end: 9 * 60 }, BlockTime { start: 9 * 60, end: 10 * 60 }];
+        data.slots = vec![vec![Some(0), None]; 2];
+        data
+    }
+
+    #[test]
+    fn counts_written_slots() {
+        let events = vec![event(0, 8 * 60, "Art"), event(0, 9 * 60, "Art"), event(1, 9 * 60, "Math")];
+        let preview = preview(&data(), &events, vec![]);
+        assert_eq!(preview.assignments.len(), 3);
+        assert_eq!(preview.conflicts(), 1);
+
+        let mut kept = data();
+        assert_eq!(apply(&mut kept, &preview, false), 2);
+        assert_eq!(kept.slots, vec![vec![Some(0), Some(1)], vec![Some(0), Some(0)]]);
+
+        let mut replaced = data();
+        assert_eq!(apply(&mut replaced, &preview, true), 3);
+        assert_eq!(replaced.slots[0], vec![Some(1), Some(1)]);
+    }
+}
@@ -0,0 +1,282 @@
+// One process per schedule file. The first one to start, the window or the daemon,
+// holds a lock and listens on a Unix socket in the runtime directory. Later launches
+// send their command through the socket and exit, instead of editing a copy of the
+// schedule which one of them would overwrite when saving.
+//
+// A request is one line with its fields separated by tabs, such as "join now" or
+// "assign\tmon\t1\tMath". The answer is "ok" or "error" on the first line, followed
+// by a message for the user.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Write};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use fs2::FileExt;
+
+use crate::cli::Command;
+use crate::paths;
+
+// Long enough for a CalDAV synchronization
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(120);
+
+// Requests are handled one at a time, so a client which sends nothing mustn't keep
+// the others waiting
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Request {
+    // Launched without a command
+    Show,
+    // Open the meeting of the block running now
+    JoinNow,
+    Sync,
+    // (name, url)
+    AddActivity(String, String),
+    // (day, block, activity) as given on the command line
+    Assign(String, String, String),
+}
+
+impl Request {
+    // Commands which act on the schedule, and so are left to the running instance.
+    // The others only read the file, which is saved shortly after every edit.
+    pub fn from_command(command: &Command) -> Option<Request> {
+        match command {
+            Command::Join => Some(Request::JoinNow),
+            Command::Sync => Some(Request::Sync),
+            Command::AddActivity { name, url } => Some(Request::AddActivity(name.clone(), url.clone())),
+            Command::Assign(day, block, activity) => {
+                Some(Request::Assign(day.clone(), block.clone(), activity.clone()))
+            }
+            _ => None,
+        }
+    }
+
+    fn encode(&self) -> String {
+        let fields: Vec<&str> = match self {
+            Request::Show => vec!["show"],
+            Request::JoinNow => vec!["join now"],
+            Request::Sync => vec!["sync"],
+            Request::AddActivity(name, url) => vec!["add-activity", name, url],
+            Request::Assign(day, block, activity) => vec!["assign", day, block, activity],
+        };
+
+        // Tabs and line breaks in a field would end it early
+        let fields: Vec<String> = fields.iter()
+            .map(|field| { field.replace(|c: char| { c == '\t' || c == '\n' || c == '\r' }, " ") })
+            .collect();
+        fields.join("\t")
+    }
+
+    fn decode(line: &str) -> Option<Request> {
+        let fields: Vec<&str> = line.trim_end_matches(|c: char| { c == '\n' || c == '\r' }).split('\t').collect();
+        match fields.as_slice() {
+            ["show"] => Some(Request::Show),
+            ["join now"] => Some(Request::JoinNow),
+            ["sync"] => Some(Request::Sync),
+            ["add-activity", name, url] => Some(Request::AddActivity(name.to_string(), url.to_string())),
+            ["assign", day, block, activity] => {
+                Some(Request::Assign(day.to_string(), block.to_string(), activity.to_string()))
+            }
+            _ => None,
+        }
+    }
+}
+
+// FNV-1a, which unlike the std hasher gives the same name in every build
+fn path_hash(path: &Path) -> u64 {
+    path.to_string_lossy().bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ u64::from(byte)).wrapping_mul(0x100000001b3)
+    })
+}
+
+// (lock, socket) for the schedule. The file may not exist yet, so only its directory
+// is resolved.
+fn runtime_paths(schedule: &Path) -> Result<(PathBuf, PathBuf), String> {
+    let schedule = match (schedule.parent(), schedule.file_name()) {
+        (Some(dir), Some(name)) => {
+            let dir = if dir == Path::new("") { Path::new(".") } else { dir };
+            fs::canonicalize(dir).map(|dir| { dir.join(name) }).unwrap_or_else(|_| { schedule.to_path_buf() })
+        }
+        _ => schedule.to_path_buf(),
+    };
+
+    let name = format!("timeplan-{:016x}", path_hash(&schedule));
+    let dir = paths::runtime_dir()?;
+    Ok((dir.join(format!("{}.lock", name)), dir.join(format!("{}.sock", name))))
+}
+
+// Holds the lock while alive. The operating system releases it when the process
+// exits, however it exits.
+pub struct Instance {
+    _lock: File,
+    socket: PathBuf,
+    listener: Option<UnixListener>,
+}
+
+impl Drop for Instance {
+    fn drop(&mut self) {
+        fs::remove_file(&self.socket).ok();
+    }
+}
+
+pub enum Start {
+    First(Instance),
+    // Another process has the schedule open, and listens on this socket
+    Running(PathBuf),
+}
+
+pub fn start(schedule: &Path) -> Result<Start, String> {
+    let (lock_path, socket) = runtime_paths(schedule)?;
+    if let Some(dir) = lock_path.parent() {
+        fs::create_dir_all(dir).map_err(|err| { format!("Failed to create {}: {}", dir.display(), err) })?;
+    }
+
+    let lock = OpenOptions::new().create(true).write(true).truncate(false).open(&lock_path)
+        .map_err(|err| { format!("Failed to open {}: {}", lock_path.display(), err) })?;
+
+    if let Err(err) = lock.try_lock_exclusive() {
+        if err.kind() == fs2::lock_contended_error().kind() {
+            return Ok(Start::Running(socket));
+        }
+
+        return Err(format!("Failed to lock {}: {}", lock_path.display(), err));
+    }
+
+    // Left behind by an instance which was killed
+    fs::remove_file(&socket).ok();
+    let listener = UnixListener::bind(&socket)
+        .map_err(|err| { format!("Failed to listen on {}: {}", socket.display(), err) })?;
+
+    Ok(Start::First(Instance { _lock: lock, socket, listener: Some(listener) }))
+}
+
+// Socket of the instance which has the schedule open, if any
+pub fn running(schedule: &Path) -> Option<PathBuf> {
+    let (lock_path, socket) = runtime_paths(schedule).ok()?;
+    let lock = File::open(&lock_path).ok()?;
+    match lock.try_lock_exclusive() {
+        Ok(_) => None,
+        Err(_) => Some(socket),
+    }
+}
+
+impl Instance {
+    // Answer requests of later launches from a background thread, with the message
+    // to show or an error
+    pub fn serve(&mut self, mut handler: impl FnMut(Request) -> Result<String, String> + Send + 'static) {
+        let listener = match self.listener.take() {
+            Some(listener) => listener,
+            None => return,
+        };
+
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = match stream {
+                    Ok(stream) => stream,
+                    Err(_) => continue,
+                };
+
+                if stream.set_read_timeout(Some(REQUEST_TIMEOUT))
+                    .and_then(|_| { stream.set_write_timeout(Some(REQUEST_TIMEOUT)) })
+                    .is_err() {
+                    continue;
+                }
+
+                let mut line = String::new();
+                let answer = match BufReader::new(&stream).read_line(&mut line) {
+                    Ok(_) => Request::decode(&line)
+                        .ok_or_else(|| { format!("Unknown request '{}'", line.trim()) })
+                        .and_then(|request| { handler(request) }),
+                    Err(err) => Err(err.to_string()),
+                };
+
+                let answer = match answer {
+                    Ok(message) => format!("ok\n{}", message),
+                    Err(err) => format!("error\n{}", err),
+                };
+                stream.write_all(answer.as_bytes()).ok();
+            }
+        });
+    }
+}
+
+// Pass the request to the running instance and return its message
+pub fn send(socket: &Path, request: &Request) -> Result<String, String> {
+    let unreachable = |err: std::io::Error| {
+        format!("timeplan is already running, but can't be reached at {}: {}", socket.display(), err)
+    };
+
+    let mut stream = UnixStream::connect(socket).map_err(unreachable)?;
+    stream.set_read_timeout(Some(ANSWER_TIMEOUT)).map_err(unreachable)?;
+    writeln!(stream, "{}", request.encode()).map_err(unreachable)?;
+
+    let mut answer = String::new();
+    stream.read_to_string(&mut answer).map_err(unreachable)?;
+
+    let mut parts = answer.splitn(2, '\n');
+    let status = parts.next().unwrap_or("");
+    let message = parts.next().unwrap_or("").trim_end().to_string();
+    match status {
+        "ok" => Ok(message),
+        "error" => Err(message),
+        _ => Err(String::from("The running timeplan gave no answer")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_requests() {
+        let requests = vec![
+            (Request::Show, "show"),
+            (Request::JoinNow, "join now"),
+            (Request::Sync, "sync"),
+            (Request::AddActivity(String::from("Math"), String::from("https://zoom.us/j/1")),
+             "add-activity\tMath\thttps://zoom.us/j/1"),
+            (Request::AddActivity(String::from("Art"), String::new()), "add-activity\tArt\t"),
+            (Request::Assign(String::from("mon"), String::from("1"), String::from("Math")), "assign\tmon\t1\tMath"),
+        ];
+
+        for (request, line) in requests {
+            assert_eq!(request.encode(), line);
+            assert_eq!(Request::decode(line), Some(request));
+        }
+    }
+
+    #[test]
+    fn keeps_fields_apart() {
+        let request = Request::AddActivity(String::from("Math\tand\nmore"), String::from("x"));
+        assert_eq!(Request::decode(&request.encode()),
+                   Some(Request::AddActivity(String::from("Math and more"), String::from("x"))));
+
+        assert_eq!(Request::decode("join now\n"), Some(Request::JoinNow));
+        assert_eq!(Request::decode("assign\tmon\t1"), None);
+        assert_eq!(Request::decode("quit"), None);
+        assert_eq!(Request::decode(""), None);
+    }
+
+    #[test]
+    fn answers_after_a_silent_client() {
+        let dir = std::env::temp_dir().join(format!("timeplan-test-instance-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        std::env::set_var("XDG_RUNTIME_DIR", &dir);
+
+        let mut instance = match start(&dir.join("plan")).unwrap() {
+            Start::First(instance) => instance,
+            Start::Running(_) => panic!("nothing else has the schedule open"),
+        };
+        instance.serve(|request| { Ok(format!("{:?}", request)) });
+
+        let socket = instance.socket.clone();
+        let _silent = UnixStream::connect(&socket).unwrap();
+        assert_eq!(send(&socket, &Request::Sync), Ok(String::from("Sync")));
+
+        drop(instance);
+        fs::remove_dir_all(&dir).ok();
+    }
+}
@@ -0,0 +1,252 @@
+// Opening meeting links, with the desktop default, a named browser or a command line.
+// Activities may override the global launcher and pick a browser profile.
+
+use std::fmt;
+use std::path::Path;
+use std::process;
+
+use crate::meeting;
+use crate::storage::Activity;
+
+// Replaced by the meeting link in command templates
+pub const URL_PLACEHOLDER: &str = "{url}";
+
+// Replaced by the activity's browser profile in command templates
+pub const PROFILE_PLACEHOLDER: &str = "{profile}";
+
+// Browsers are recognized by their executable, such as /usr/bin/google-chrome-stable
+static FIREFOX_LIKE: &[&str] = &["firefox", "librewolf", "waterfox", "icecat"];
+static CHROME_LIKE: &[&str] = &["chrom", "brave", "edge", "vivaldi", "opera"];
+
+#[derive(Debug, Clone, Default, Eq, PartialEq, Savefile, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", content = "value", rename_all = "kebab-case")]
+pub enum Launcher {
+    // Whatever xdg-open picks for the link
+    #[default]
+    SystemDefault,
+
+    // Executable of a browser, such as firefox
+    Browser(String),
+
+    // Command line, such as `firefox --new-window {url}`
+    Command(String),
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum LauncherKind {
+    SystemDefault,
+    Browser,
+    Command,
+}
+
+impl LauncherKind {
+    pub const ALL: [LauncherKind; 3] = [LauncherKind::SystemDefault, LauncherKind::Browser,
+                                        LauncherKind::Command];
+}
+
+impl fmt::Display for LauncherKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LauncherKind::SystemDefault => write!(f, "System default"),
+            LauncherKind::Browser => write!(f, "Browser"),
+            LauncherKind::Command => write!(f, "Command"),
+        }
+    }
+}
+
+impl Launcher {
+    pub fn kind(&self) -> LauncherKind {
+        match self {
+            Launcher::SystemDefault => LauncherKind::SystemDefault,
+            Launcher::Browser(_) => LauncherKind::Browser,
+            Launcher::Command(_) => LauncherKind::Command,
+        }
+    }
+
+    // Browser name or command template, empty for the system default
+    pub fn text(&self) -> &str {
+        match self {
+            Launcher::SystemDefault => "",
+            Launcher::Browser(text) | Launcher::Command(text) => text,
+        }
+    }
+
+    pub fn with_kind(kind: LauncherKind, text: &str) -> Launcher {
+        match kind {
+            LauncherKind::SystemDefault => Launcher::SystemDefault,
+            LauncherKind::Browser => Launcher::Browser(text.to_string()),
+            LauncherKind::Command => Launcher::Command(text.to_string()),
+        }
+    }
+}
+
+// Split a command line into arguments, honouring single and double quotes
+fn split_command(command: &str) -> Vec<String> {
+    let mut args = vec![];
+    let mut current: Option<String> = None;
+    let mut quote: Option<char> = None;
+
+    for c in command.chars() {
+        match (quote, c) {
+            (Some(open), c) if c == open => quote = None,
+            (Some(_), c) => current.get_or_insert_with(String::new).push(c),
+            (None, '"') | (None, '\'') => {
+                quote = Some(c);
+                current.get_or_insert_with(String::new);
+            }
+            (None, c) if c.is_whitespace() => args.extend(current.take()),
+            (None, c) => current.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    args.extend(current);
+    args
+}
+
+// The link replaces {url} in the template, or is appended if there is none
+pub fn command_line(template: &str, url: &str, profile: &str) -> Vec<String> {
+    let mut args = split_command(template);
+    let has_url = args.iter().any(|arg| { arg.contains(URL_PLACEHOLDER) });
+    for arg in args.iter_mut() {
+        *arg = arg.replace(URL_PLACEHOLDER, url).replace(PROFILE_PLACEHOLDER, profile);
+    }
+
+    if !has_url {
+        args.push(url.to_string());
+    }
+
+    args
+}
+
+fn expand_home(path: &str) -> String {
+    match (path.strip_prefix("~/"), std::env::var("HOME")) {
+        (Some(rest), Ok(home)) => format!("{}/{}", home.trim_end_matches('/'), rest),
+        _ => path.to_string(),
+    }
+}
+
+// Browser with the given profile directory, which is created by the browser if missing
+fn browser_line(browser: &str, profile: &str, url: &str) -> Result<Vec<String>, String> {
+    let browser = browser.trim();
+    let mut args = vec![browser.to_string()];
+    if !profile.trim().is_empty() {
+        let name = Path::new(browser).file_name()
+            .map(|name| { name.to_string_lossy().to_lowercase() })
+            .unwrap_or_default();
+        let profile = expand_home(profile.trim());
+
+        if FIREFOX_LIKE.iter().any(|known| { name.contains(known) }) {
+            args.push(String::from("--profile"));
+            args.push(profile);
+        } else if CHROME_LIKE.iter().any(|known| { name.contains(known) }) {
+            args.push(format!("--user-data-dir={}", profile));
+        } else {
+            return Err(format!("Don't know how to open a profile with {}, use a command instead", browser));
+        }
+    }
+
+    args.push(url.to_string());
+    Ok(args)
+}
+
+// Browsers keep running, so they are waited for in the background
+fn spawn(args: &[String]) -> Result<(), String> {
+    let (program, args) = args.split_first()
+        .ok_or_else(|| { String::from("The launch command is empty") })?;
+
+    let mut child = process::Command::new(program)
+        .args(args)
+        .spawn()
+        .map_err(|err| { format!("Failed to run {}: {}", program, err) })?;
+
+    std::thread::spawn(move || { child.wait().ok(); });
+    Ok(())
+}
+
+pub fn launch(launcher: &Launcher, profile: &str, url: &str) -> Result<(), String> {
+    if url.trim().is_empty() {
+        return Err(String::from("No meeting link is set"));
+    }
+
+    match launcher {
+        Launcher::SystemDefault if !profile.trim().is_empty() => {
+            Err(String::from("A browser profile needs a browser or a command to open it with"))
+        }
+        Launcher::SystemDefault => {
+            match open::that(url) {
+                Ok(status) if status.success() => Ok(()),
+                Ok(status) => Err(format!("Failed to open {}: the system opener {}", url, status)),
+                Err(err) => Err(format!("Failed to open {}: {}", url, err)),
+            }
+        }
+        Launcher::Browser(browser) if browser.trim().is_empty() => {
+            Err(String::from("No browser is set to open meeting links with"))
+        }
+        Launcher::Browser(browser) => {
+            spawn(&browser_line(browser, profile, url)?)
+        }
+        Launcher::Command(template) if template.trim().is_empty() => {
+            Err(String::from("No command is set to open meeting links with"))
+        }
+        Launcher::Command(template) => {
+            spawn(&command_line(template, url, &expand_home(profile.trim())))
+        }
+    }
+}
+
+// The provider's app if asked for and there is one, otherwise the activity's own
+// launcher if it has one, otherwise the global one
+pub fn launch_activity(global: &Launcher, activity: &Activity) -> Result<(), String> {
+    let app_url = meeting::parse(&activity.url).and_then(|link| { link.app_url });
+    if let (true, Some(app_url)) = (activity.open_in_app, app_url) {
+        return launch(&Launcher::SystemDefault, "", &app_url)
+            .map_err(|err| { format!("{}: {}, is the app installed?", activity.name, err) });
+    }
+
+    let launcher = activity.launcher.as_ref().unwrap_or(global);
+    launch(launcher, &activity.profile, &activity.url)
+        .map_err(|err| { format!("{}: {}", activity.name, err) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| { arg.to_string() }).collect()
+    }
+
+    #[test]
+    fn splits_commands() {
+        assert_eq!(split_command("firefox  --new-window\t{url}"), args(&["firefox", "--new-window", "{url}"]));
+        assert_eq!(split_command("\"/opt/My Browser/browser\" --title='Class of 2021' x"),
+                   args(&["/opt/My Browser/browser", "--title=Class of 2021", "x"]));
+        assert_eq!(split_command("echo \"it's\" '' \"\""), args(&["echo", "it's", "", ""]));
+
+        // An unterminated quote runs to the end
+        assert_eq!(split_command("open 'a b"), args(&["open", "a b"]));
+
+        assert!(split_command("").is_empty());
+        assert!(split_command("   ").is_empty());
+    }
+
+    #[test]
+    fn fills_in_command_templates() {
+        let url = "https://zoom.us/j/1";
+        assert_eq!(command_line("firefox -P '{profile}' --new-window {url}", url, "School Profile"),
+                   args(&["firefox", "-P", "School Profile", "--new-window", url]));
+        assert_eq!(command_line("'my browser'", url, ""), args(&["my browser", url]));
+        assert_eq!(command_line("", url, ""), args(&[url]));
+    }
+
+    #[test]
+    fn adds_browser_profiles() {
+        let url = "https://zoom.us/j/1";
+        assert_eq!(browser_line(" firefox ", "", url), Ok(args(&["firefox", url])));
+        assert_eq!(browser_line("/usr/bin/firefox", "/home/me/school", url),
+                   Ok(args(&["/usr/bin/firefox", "--profile", "/home/me/school", url])));
+        assert_eq!(browser_line("google-chrome-stable", "/home/me/school", url),
+                   Ok(args(&["google-chrome-stable", "--user-data-dir=/home/me/school", url])));
+        assert!(browser_line("lynx", "/home/me/school", url).is_err());
+    }
+}
@@ -5,17 +5,33 @@ extern crate savefile_derive;
 
 use std::fs;
 
+mod api;
+mod autojoin;
+mod autosave;
+mod caldav;
+mod clock;
+mod cli;
+mod commands;
+mod csv_format;
+mod daemon;
+mod ical;
+mod import;
+mod instance;
+mod launcher;
+mod meeting;
+mod notification;
+mod paths;
+mod reminder;
+mod status;
+mod storage;
 mod style;
+mod text_format;
+mod trigger;
 
+use storage::{Activity, BlockTime, PersistentData, format_clock, parse_clock, resize_block_times};
 
-#[derive(Debug, Clone, Eq, PartialEq, Default, Savefile)]
-struct Activity {
-    name: String,
-    url: String,
-    id: usize,
-}
 
-#[derive(Debug, Clone, Eq, PartialEq, Savefile)]
+#[derive(Debug, Clone, Eq, PartialEq)]
 struct ActivityPickListItem {
     index: usize,
     label: String,
@@ -29,18 +45,44 @@ impl ToString for ActivityPickListItem {
 
 #[derive(Default)]
 struct ScheduledActivity {
-    activity: Option<ActivityPickListItem>,
+    // Activity ID, labels are looked up when rendering
+    activity: Option<usize>,
     pick_state: iced::pick_list::State<ActivityPickListItem>,
     link_state: iced::button::State,
 }
 
-type DayPlan = [ScheduledActivity; 6];
-type TimePlan = [DayPlan; 5];
+type DayPlan = Vec<ScheduledActivity>;
+type TimePlan = Vec<DayPlan>;
+
+const DEFAULT_DAYS: usize = 5;
+const DEFAULT_BLOCKS: usize = 6;
+const MAX_BLOCKS: usize = 12;
+
+fn new_time_plan(days: usize, blocks: usize) -> TimePlan {
+    let mut plan = TimePlan::new();
+    resize_time_plan(&mut plan, days, blocks);
+    plan
+}
+
+fn resize_time_plan(plan: &mut TimePlan, days: usize, blocks: usize) {
+    plan.resize_with(days, DayPlan::new);
+    for day in plan.iter_mut() {
+        day.resize_with(blocks, ScheduledActivity::default);
+    }
+}
+
+// (days, blocks) of the plan
+fn time_plan_size(plan: &TimePlan) -> (usize, usize) {
+    (plan.len(), plan.first().map(|day| day.len()).unwrap_or(0))
+}
 
 #[derive(Debug, Eq, PartialEq, Copy, Clone)]
 enum NewActivityTextInputs {
     Name,
     URL,
+    Launcher,
+    Profile,
+    AutoJoin,
 }
 
 impl NewActivityTextInputs {
@@ -48,6 +90,24 @@ impl NewActivityTextInputs {
         match *self {
             NewActivityTextInputs::Name => { String::from("Enter activity name") }
             NewActivityTextInputs::URL => { String::from("Enter activity URL") }
+            NewActivityTextInputs::Launcher => { String::from("Browser, or command with {url}") }
+            NewActivityTextInputs::Profile => { String::from("Browser profile directory (optional)") }
+            NewActivityTextInputs::AutoJoin => { String::from("Auto-join minutes before start (optional)") }
+        }
+    }
+}
+
+// Launcher choice of an activity, None uses the global setting
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+struct ActivityLauncherItem {
+    kind: Option<launcher::LauncherKind>,
+}
+
+impl std::fmt::Display for ActivityLauncherItem {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.kind {
+            Some(kind) => write!(f, "{}", kind),
+            None => write!(f, "Global setting"),
         }
     }
 }
@@ -57,6 +117,16 @@ struct ActivityCreateParams {
     name: String,
     url_state: iced::text_input::State,
     url: String,
+    launcher_pick: iced::pick_list::State<ActivityLauncherItem>,
+    launcher_kind: Option<launcher::LauncherKind>,
+    launcher_text_state: iced::text_input::State,
+    launcher_text: String,
+    profile_state: iced::text_input::State,
+    profile: String,
+    open_in_app: bool,
+    autojoin_state: iced::text_input::State,
+    autojoin: String,
+    mute_reminders: bool,
 
     new_activity_submit_btn: iced::button::State,
     new_activity_cancel_btn: iced::button::State,
@@ -81,6 +151,16 @@ impl ActivitiesArea {
                 name: String::from(""),
                 url_state: iced::text_input::State::default(),
                 url: String::from(""),
+                launcher_pick: iced::pick_list::State::default(),
+                launcher_kind: None,
+                launcher_text_state: iced::text_input::State::default(),
+                launcher_text: String::from(""),
+                profile_state: iced::text_input::State::default(),
+                profile: String::from(""),
+                open_in_app: false,
+                autojoin_state: iced::text_input::State::default(),
+                autojoin: String::from(""),
+                mute_reminders: false,
                 new_activity_submit_btn: iced::button::State::default(),
                 new_activity_cancel_btn: iced::button::State::default(),
             },
@@ -94,11 +174,335 @@ impl ActivitiesArea {
     }
 }
 
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+enum BlockTimeField {
+    Start,
+    End,
+}
+
+#[derive(Debug, Eq, PartialEq, Copy, Clone)]
+enum CalDavField {
+    Url,
+    Username,
+    Password,
+}
+
+struct BlockTimeInput {
+    start_state: iced::text_input::State,
+    start: String,
+    end_state: iced::text_input::State,
+    end: String,
+}
+
+impl BlockTimeInput {
+    fn new(time: &BlockTime) -> BlockTimeInput {
+        BlockTimeInput {
+            start_state: iced::text_input::State::default(),
+            start: format_clock(time.start),
+            end_state: iced::text_input::State::default(),
+            end: format_clock(time.end),
+        }
+    }
+}
+
+struct GridArea {
+    add_day_btn: iced::button::State,
+    remove_day_btn: iced::button::State,
+    add_block_btn: iced::button::State,
+    remove_block_btn: iced::button::State,
+
+    // Start/end inputs for each block
+    time_inputs: Vec<BlockTimeInput>,
+}
+
+impl GridArea {
+    fn new() -> GridArea {
+        GridArea {
+            add_day_btn: iced::button::State::default(),
+            remove_day_btn: iced::button::State::default(),
+            add_block_btn: iced::button::State::default(),
+            remove_block_btn: iced::button::State::default(),
+            time_inputs: vec![],
+        }
+    }
+
+    fn reset_time_inputs(&mut self, times: &[BlockTime]) {
+        self.time_inputs = times.iter().map(BlockTimeInput::new).collect();
+    }
+}
+
+struct SettingsArea {
+    remove_backup_btn: iced::button::State,
+    add_backup_btn: iced::button::State,
+    launcher_pick: iced::pick_list::State<launcher::LauncherKind>,
+    launcher_text_state: iced::text_input::State,
+    fewer_minutes_btn: iced::button::State,
+    more_minutes_btn: iced::button::State,
+    fewer_reminder_minutes_btn: iced::button::State,
+    more_reminder_minutes_btn: iced::button::State,
+    api_port_state: iced::text_input::State,
+    // Port as typed, applied when submitted
+    api_port: String,
+}
+
+impl SettingsArea {
+    fn new() -> SettingsArea {
+        SettingsArea {
+            remove_backup_btn: iced::button::State::default(),
+            add_backup_btn: iced::button::State::default(),
+            launcher_pick: iced::pick_list::State::default(),
+            launcher_text_state: iced::text_input::State::default(),
+            fewer_minutes_btn: iced::button::State::default(),
+            more_minutes_btn: iced::button::State::default(),
+            fewer_reminder_minutes_btn: iced::button::State::default(),
+            more_reminder_minutes_btn: iced::button::State::default(),
+            api_port_state: iced::text_input::State::default(),
+            api_port: api::DEFAULT_PORT.to_string(),
+        }
+    }
+}
+
+// Dismissable message about something which failed in the background of an action
+struct ErrorBanner {
+    message: String,
+    dismiss_btn: iced::button::State,
+}
+
+impl ErrorBanner {
+    fn new(message: String) -> ErrorBanner {
+        ErrorBanner {
+            message,
+            dismiss_btn: iced::button::State::default(),
+        }
+    }
+}
+
+// Reminder shown in the window when there is no notification service
+struct ReminderBanner {
+    reminder: trigger::Trigger,
+    title: String,
+    body: String,
+    join_btn: iced::button::State,
+    snooze_btn: iced::button::State,
+    dismiss_btn: iced::button::State,
+}
+
+impl ReminderBanner {
+    fn new(reminder: trigger::Trigger, title: String, body: String) -> ReminderBanner {
+        ReminderBanner {
+            reminder,
+            title,
+            body,
+            join_btn: iced::button::State::default(),
+            snooze_btn: iced::button::State::default(),
+            dismiss_btn: iced::button::State::default(),
+        }
+    }
+}
+
+struct CalendarArea {
+    path_state: iced::text_input::State,
+    path: String,
+    export_btn: iced::button::State,
+    import_btn: iced::button::State,
+
+    // Result of the last export, or why the file couldn't be imported
+    status: Option<String>,
+
+    caldav_url_state: iced::text_input::State,
+    caldav_username_state: iced::text_input::State,
+    caldav_password_state: iced::text_input::State,
+    fewer_sync_minutes_btn: iced::button::State,
+    more_sync_minutes_btn: iced::button::State,
+    sync_btn: iced::button::State,
+
+    // Result of the last synchronization
+    sync_status: Option<String>,
+}
+
+impl CalendarArea {
+    fn new() -> CalendarArea {
+        let path = paths::data_dir()
+            .map(|dir| { dir.join("timetable.ics") })
+            .unwrap_or_else(|| { std::path::PathBuf::from("timetable.ics") });
+
+        CalendarArea {
+            path_state: iced::text_input::State::default(),
+            path: path.to_string_lossy().into_owned(),
+            export_btn: iced::button::State::default(),
+            import_btn: iced::button::State::default(),
+            status: None,
+            caldav_url_state: iced::text_input::State::default(),
+            caldav_username_state: iced::text_input::State::default(),
+            caldav_password_state: iced::text_input::State::default(),
+            fewer_sync_minutes_btn: iced::button::State::default(),
+            more_sync_minutes_btn: iced::button::State::default(),
+            sync_btn: iced::button::State::default(),
+            sync_status: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct ColumnPickListItem {
+    column: Option<usize>,
+    label: String,
+}
+
+impl ToString for ColumnPickListItem {
+    fn to_string(&self) -> String {
+        self.label.clone()
+    }
+}
+
+struct SpreadsheetArea {
+    path_state: iced::text_input::State,
+    path: String,
+    export_btn: iced::button::State,
+    import_btn: iced::button::State,
+
+    // Result of the last export, or why the file couldn't be imported
+    status: Option<String>,
+
+    // Loaded file waiting for its columns to be mapped
+    table: Option<csv_format::CsvTable>,
+    mapping: csv_format::ColumnMapping,
+    column_picks: Vec<iced::pick_list::State<ColumnPickListItem>>,
+    preview_btn: iced::button::State,
+    cancel_btn: iced::button::State,
+}
+
+impl SpreadsheetArea {
+    fn new() -> SpreadsheetArea {
+        let path = paths::data_dir()
+            .map(|dir| { dir.join("timetable.csv") })
+            .unwrap_or_else(|| { std::path::PathBuf::from("timetable.csv") });
+
+        SpreadsheetArea {
+            path_state: iced::text_input::State::default(),
+            path: path.to_string_lossy().into_owned(),
+            export_btn: iced::button::State::default(),
+            import_btn: iced::button::State::default(),
+            status: None,
+            table: None,
+            mapping: csv_format::ColumnMapping::default(),
+            column_picks: csv_format::CsvField::ALL.iter().map(|_| { Default::default() }).collect(),
+            preview_btn: iced::button::State::default(),
+            cancel_btn: iced::button::State::default(),
+        }
+    }
+}
+
+// Import from any source, waiting to be confirmed
+struct ImportArea {
+    preview: Option<import::ImportPreview>,
+    // Result of the last applied import
+    status: Option<String>,
+    apply_keep_btn: iced::button::State,
+    apply_replace_btn: iced::button::State,
+    cancel_btn: iced::button::State,
+}
+
+impl ImportArea {
+    fn new() -> ImportArea {
+        ImportArea {
+            preview: None,
+            status: None,
+            apply_keep_btn: iced::button::State::default(),
+            apply_replace_btn: iced::button::State::default(),
+            cancel_btn: iced::button::State::default(),
+        }
+    }
+}
+
+// Shown instead of crashing when the schedule file can't be read
+struct LoadFailure {
+    message: String,
+    backups: Vec<(u64, std::path::PathBuf)>,
+    restore_btns: Vec<iced::button::State>,
+    dismiss_btn: iced::button::State,
+}
+
+impl LoadFailure {
+    fn new(message: String, backups: Vec<(u64, std::path::PathBuf)>) -> LoadFailure {
+        LoadFailure {
+            message,
+            restore_btns: vec![iced::button::State::default(); backups.len()],
+            backups,
+            dismiss_btn: iced::button::State::default(),
+        }
+    }
+}
+
 struct Schedule {
     activity_area: ActivitiesArea,
+    grid_area: GridArea,
+    settings_area: SettingsArea,
+    calendar_area: CalendarArea,
+    spreadsheet_area: SpreadsheetArea,
+    import_area: ImportArea,
+    load_failure: Option<LoadFailure>,
+    error_banner: Option<ErrorBanner>,
     activities: Vec<Activity>,
+    // IDs are never reused, even after the activity is removed
+    next_activity_id: usize,
     time_plan : TimePlan,
+    block_times: Vec<BlockTime>,
+    backup_count: usize,
+    launcher: launcher::Launcher,
+    table_scroll: iced::scrollable::State,
+    side_scroll: iced::scrollable::State,
     theme: style::Theme,
+    // Schedule file, resolved in main() from --config, $TIMEPLAN_CONFIG or the XDG config directory
+    path: String,
+    autosaver: autosave::Autosaver,
+    // Local time of the last clock tick
+    now: chrono::NaiveDateTime,
+    autojoin: autojoin::AutoJoinSettings,
+    autojoiner: autojoin::AutoJoiner,
+    cancel_autojoin_btn: iced::button::State,
+    reminders: reminder::ReminderSettings,
+    reminder_queue: reminder::Reminders,
+    notifier: Box<dyn notification::Notifier>,
+    reminder_banner: Option<ReminderBanner>,
+
+    // Messages from background threads, handled on the next tick
+    messages_tx: std::sync::mpsc::Sender<ScheduleMessage>,
+    messages_rx: std::sync::mpsc::Receiver<ScheduleMessage>,
+
+    api: api::ApiSettings,
+    api_server: Option<api::Server>,
+    // State answered by the API and later launches, updated on every tick
+    snapshot: std::sync::Arc<std::sync::Mutex<PersistentData>>,
+
+    caldav: caldav::CalDavSettings,
+    caldav_synced: Vec<caldav::SyncedSlot>,
+    // Synchronizations run in the background and report back here
+    sync_tx: std::sync::mpsc::Sender<SyncOutcome>,
+    sync_rx: std::sync::mpsc::Receiver<SyncOutcome>,
+    sync_running: bool,
+    last_sync: Option<std::time::Instant>,
+
+    // Number of changes to the saved data, to tell whether the schedule was edited
+    // while a synchronization was running
+    edits: u64,
+
+    // Lock on the schedule file, through which later launches send their commands
+    lock: Option<instance::Instance>,
+}
+
+// Passed from main() to the window
+struct Launch {
+    path: String,
+    // None if the schedule couldn't be locked, see instance::start
+    lock: Option<instance::Instance>,
+}
+
+struct SyncOutcome {
+    // Value of `edits` when the synchronization started
+    edits: u64,
+    data: PersistentData,
+    result: Result<caldav::Report, String>,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq)]
@@ -109,6 +513,12 @@ enum ScheduleMessage {
     // New activity text updated
     NewActivityTextChanged(NewActivityTextInputs, String),
 
+    // Launcher of the edited activity chosen, None for the global setting
+    NewActivityLauncherChosen(Option<launcher::LauncherKind>),
+
+    // Whether the edited activity opens in the meeting app
+    NewActivityOpenInAppToggled(bool),
+
     // New activity should be created
     NewActivitySubmitted,
 
@@ -124,25 +534,209 @@ enum ScheduleMessage {
     // Activity chosen (day, block, idx)
     ActivityChosen(usize, usize, Option<usize>),
 
-    // Launch meeting
-    LaunchMeeting(String),
+    // Launch meeting of the activity (id)
+    LaunchMeeting(usize),
+
+    // Change the size of the plan (days, blocks)
+    ResizePlan(usize, usize),
+
+    // Block start/end text updated (block, field, text)
+    BlockTimeChanged(usize, BlockTimeField, String),
+
+    // Number of backups to keep
+    BackupCountChanged(usize),
+
+    // How meeting links are opened
+    LauncherKindChosen(launcher::LauncherKind),
+
+    // Browser name or command template updated
+    LauncherTextChanged(String),
+
+    // Hide the error banner
+    DismissError,
+
+    // Clock tick, once a second
+    Tick,
+
+    // Join every activity automatically, or only those which opted in
+    AutoJoinToggled(bool),
+
+    // Minutes before the start of a block to join it
+    AutoJoinMinutesChanged(usize),
+
+    // Don't join the block announced by the countdown
+    CancelAutoJoin,
+
+    // Remind of upcoming blocks
+    RemindersToggled(bool),
+
+    // Minutes before the start of a block to remind of it
+    ReminderMinutesChanged(usize),
+
+    // Reminder was answered in the notification or the banner
+    ReminderAnswered(trigger::Trigger, reminder::Answer),
+
+    // Never remind of the activity being edited
+    NewActivityMuteToggled(bool),
+
+    // Add an activity without the editor, (name, url)
+    AddActivity(String, String),
+
+    // Serve the HTTP API
+    ApiToggled(bool),
+
+    // Port of the HTTP API was edited
+    ApiPortChanged(String),
+
+    // Use the edited port
+    ApiPortSubmitted,
+
+    // Restore the backup with given index after a failed load
+    RestoreBackup(usize),
+
+    // Continue with an empty schedule after a failed load
+    DismissLoadFailure,
+
+    // Calendar file path updated
+    CalendarPathChanged(String),
+
+    // Export the timetable to the calendar file
+    ExportCalendar,
+
+    // Read the calendar file and preview what importing it would change
+    ImportCalendar,
+
+    // CalDAV setting text updated
+    CalDavTextChanged(CalDavField, String),
+
+    // Synchronize with the CalDAV calendar periodically
+    CalDavToggled(bool),
+
+    // Minutes between synchronizations
+    CalDavMinutesChanged(usize),
+
+    // Keep the calendar's version of slots changed on both sides
+    CalDavRemoteWinsToggled(bool),
+
+    // Synchronize with the CalDAV calendar now
+    SyncCalendar,
+
+    // CSV file path updated
+    CsvPathChanged(String),
+
+    // Export the timetable to the CSV file
+    ExportCsv,
+
+    // Read the CSV file and ask which columns hold which fields
+    LoadCsv,
+
+    // Column chosen for a CSV field (field, column)
+    CsvColumnChosen(csv_format::CsvField, Option<usize>),
+
+    // Preview importing the CSV file with the chosen columns
+    PreviewCsvImport,
+
+    // Discard the loaded CSV file
+    CancelCsvImport,
+
+    // Apply the previewed import (replace conflicting slots)
+    ApplyImport(bool),
+
+    // Discard the previewed import
+    CancelImport,
+}
+
+impl ScheduleMessage {
+    // Whether handling the message changes what is saved to disk
+    fn changes_data(&self) -> bool {
+        matches!(self,
+                 ScheduleMessage::NewActivityRequest |
+                 ScheduleMessage::NewActivitySubmitted |
+                 ScheduleMessage::RemoveActivity(_) |
+                 ScheduleMessage::ActivityChosen(_, _, _) |
+                 ScheduleMessage::ResizePlan(_, _) |
+                 ScheduleMessage::BlockTimeChanged(_, _, _) |
+                 ScheduleMessage::BackupCountChanged(_) |
+                 ScheduleMessage::LauncherKindChosen(_) |
+                 ScheduleMessage::LauncherTextChanged(_) |
+                 ScheduleMessage::AutoJoinToggled(_) |
+                 ScheduleMessage::AutoJoinMinutesChanged(_) |
+                 ScheduleMessage::RemindersToggled(_) |
+                 ScheduleMessage::ReminderMinutesChanged(_) |
+                 ScheduleMessage::AddActivity(_, _) |
+                 ScheduleMessage::ApiToggled(_) |
+                 ScheduleMessage::ApiPortSubmitted |
+                 ScheduleMessage::CalDavTextChanged(_, _) |
+                 ScheduleMessage::CalDavToggled(_) |
+                 ScheduleMessage::CalDavMinutesChanged(_) |
+                 ScheduleMessage::CalDavRemoteWinsToggled(_) |
+                 ScheduleMessage::ApplyImport(_))
+    }
 }
 
 static CAPTIONS: &'static [&'static str] =
-&["Monday", "Tuesday", "Wednesday", "Thursday", "Friday"];
+&["Monday", "Tuesday", "Wednesday", "Thursday", "Friday", "Saturday", "Sunday"];
+
+// Drawn across all days, with the time in today's column
+fn now_line<'a>(label: String) -> iced::Row<'a, ScheduleMessage> {
+    iced::Row::new()
+        .push(iced::Text::new(label)
+              .size(14)
+              .color(style::NOW_COLOR)
+              .width(iced::Length::Units(40)))
+        .push(iced::Rule::horizontal(16).style(style::NowLine))
+        .height(iced::Length::Units(16))
+}
+
+fn activity_name(data: &PersistentData, id: Option<usize>) -> Option<&str> {
+    let id = id?;
+    data.activities.iter()
+        .find(|activity| { activity.id == id })
+        .map(|activity| { activity.name.as_str() })
+}
+
+// "Now: Math until 10:30 · Next up: Physics in 12 min"
+fn clock_banner(data: &PersistentData, now: &chrono::NaiveDateTime) -> String {
+    let mut parts = vec![];
+    if let Some(current) = clock::current(data, now) {
+        if let Some(name) = activity_name(data, current.activity) {
+            parts.push(format!("Now: {} until {}", name, current.end.format("%H:%M")));
+        }
+    }
+
+    match clock::next(data, now) {
+        Some(next) => {
+            let name = activity_name(data, next.activity).unwrap_or("?");
+            parts.push(format!("Next up: {} {}", name, clock::describe_start(now, &next.start)));
+        }
+        None => parts.push(String::from("Nothing planned")),
+    }
+
+    parts.join(" \u{b7} ")
+}
 
-fn time_plan_layout<'a>(plan: &'a mut TimePlan, activities: &mut Vec<Activity>, theme: style::Theme)
+fn time_plan_layout<'a>(plan: &'a mut TimePlan, times: &[BlockTime],
+                        activities: &mut Vec<Activity>, theme: style::Theme,
+                        now: &chrono::NaiveDateTime)
         -> iced::Element<'a, ScheduleMessage> {
     let mut content = iced::Row::<ScheduleMessage>::new()
         .push(iced::Rule::vertical(10).style(theme));
 
+    // The line goes inside the running block, or before the next one
+    let (today, minutes) = clock::plan_position(now);
+    let line_block = times.iter().position(|time| { minutes < time.end }).unwrap_or(times.len());
+    let inside_block = times.get(line_block).map(|time| { time.start <= minutes }).unwrap_or(false);
+    let show_line = today < plan.len();
+    let line_label = |day_idx| {
+        if day_idx == today { now.format("%H:%M").to_string() } else { String::new() }
+    };
+
     let pick_list_items: Vec<ActivityPickListItem> =
         activities.iter().map(|activity| {
             ActivityPickListItem {index: activity.id, label: activity.name.clone()}
         }).collect();
 
     for (day_idx, day) in plan.iter_mut().enumerate() {
-        let mut clock_begin = 8; // 08:00
         let mut day_column =
             iced::Column::<ScheduleMessage>::new()
             .push(iced::Rule::horizontal(0).style(theme))
@@ -153,42 +747,72 @@ fn time_plan_layout<'a>(plan: &'a mut TimePlan, activities: &mut Vec<Activity>,
         let length = day.len();
 
         for (block_idx, block) in day.iter_mut().enumerate() {
+            if show_line && !inside_block && block_idx == line_block {
+                day_column = day_column.push(now_line(line_label(day_idx)));
+            }
+
             let pick_list = iced::pick_list::PickList::new(
                 &mut block.pick_state,
                 pick_list_items.clone(),
-                block.activity.clone(),
+                block.activity.and_then(|id| {
+                    pick_list_items.iter().find(|item| { item.index == id }).cloned()
+                }),
                 move |sel| { ScheduleMessage::ActivityChosen(day_idx, block_idx, Some(sel.index)) })
                 .style(theme);
 
+            // Meeting ID and passcode of recognized links
+            let mut details = String::new();
+
             let mut url_btn = iced::Button::new(&mut block.link_state, iced::Text::new("Meeting"));
-            if let Some(activity) = &mut block.activity {
-                let url = find_activity(activities, Some(activity.index))
-                    .map(|a| a.url.clone()).unwrap();
+            if let Some(activity) = find_activity(activities, block.activity) {
+                if let Some(link) = meeting::parse(&activity.url) {
+                    details = link.summary();
+                }
 
                 url_btn = url_btn
-                    .on_press(ScheduleMessage::LaunchMeeting(url))
+                    .on_press(ScheduleMessage::LaunchMeeting(activity.id))
                     .style(theme);
             } else {
                 url_btn = url_btn
                     .style(style::InactiveButton);
             }
 
+            let running = show_line && inside_block && block_idx == line_block;
             let mut block_column = iced::Column::new()
-                .push(iced::Text::new(format!("{:0>2}:00", clock_begin))
+                .push(iced::Text::new(times[block_idx].to_string())
                       .horizontal_alignment(iced::HorizontalAlignment::Left)
                       .size(16)
-                      .color(iced::Color::from_rgb(0.5, 0.5, 0.5)))
-                .push(iced::Space::with_height(iced::Length::Units(10)))
-                .push(iced::Container::new(
+                      .color(iced::Color::from_rgb(0.5, 0.5, 0.5)));
+
+            if running {
+                block_column = block_column.push(now_line(line_label(day_idx)));
+            }
+
+            let block_container = iced::Container::new(
                         iced::Column::new()
                         .push(pick_list.width(iced::Length::Fill))
                         .push(iced::Space::with_height(iced::Length::Units(20)))
                         .push(iced::Container::new(url_btn)
                               .align_x(iced::Align::Center)
+                              .width(iced::Length::Fill))
+                        .push(iced::Container::new(iced::Text::new(details)
+                                                   .size(14)
+                                                   .color(iced::Color::from_rgb(0.5, 0.5, 0.5)))
+                              .align_x(iced::Align::Center)
                               .width(iced::Length::Fill)))
-                    .style(theme)
                     .width(iced::Length::Fill)
-                    .align_x(iced::Align::Center));
+                    .align_x(iced::Align::Center);
+
+            // Only today's block is highlighted, the line marks the time on other days
+            let block_container = if running && day_idx == today {
+                block_container.style(style::CurrentBlock)
+            } else {
+                block_container.style(theme)
+            };
+
+            block_column = block_column
+                .push(iced::Space::with_height(iced::Length::Units(10)))
+                .push(block_container);
 
             if block_idx != length - 1 {
                 block_column = block_column
@@ -200,7 +824,10 @@ fn time_plan_layout<'a>(plan: &'a mut TimePlan, activities: &mut Vec<Activity>,
             }
 
             day_column = day_column.push(block_column);
-            clock_begin += 2; // + 02:00
+        }
+
+        if show_line && line_block == length {
+            day_column = day_column.push(now_line(line_label(day_idx)));
         }
 
         content = content
@@ -208,39 +835,203 @@ fn time_plan_layout<'a>(plan: &'a mut TimePlan, activities: &mut Vec<Activity>,
             .push(iced::Rule::vertical(10).style(theme))
     }
 
-    content
-        .max_height(866)
-        .into()
+    content.into()
 }
 
-#[derive(Savefile, Default)]
-struct PersistentData {
-    activities: Vec<Activity>,
-    plan: [[Option<ActivityPickListItem>; 6]; 5],
-}
+impl Schedule {
+    // Only what the clock banner and the auto-join countdown look at, for every frame
+    fn clock_data(&self) -> PersistentData {
+        let mut data = PersistentData::new();
+        data.activities = self.activities.clone();
+        data.slots = self.time_plan.iter().map(|day| {
+            day.iter().map(|block| { block.activity }).collect()
+        }).collect();
+        data.block_times = self.block_times.clone();
+        data.autojoin = self.autojoin;
+        data
+    }
+
+    fn to_data(&self) -> PersistentData {
+        let mut data = self.clock_data();
+        data.next_activity_id = self.next_activity_id;
+        data.backup_count = self.backup_count;
+        data.launcher = self.launcher.clone();
+        data.reminders = self.reminders;
+        data.api = self.api;
+        data.caldav = self.caldav.clone();
+        data.caldav_synced = self.caldav_synced.clone();
+        data
+    }
+
+    fn apply_data(&mut self, data: PersistentData) {
+        self.next_activity_id = data.next_activity_id;
+        self.activities = data.activities;
+        self.block_times = data.block_times;
+        self.backup_count = data.backup_count;
+        self.launcher = data.launcher;
+        self.autojoin = data.autojoin;
+        self.reminders = data.reminders;
+        let api_changed = self.api != data.api;
+        self.api = data.api;
+        self.settings_area.api_port = data.api.port.to_string();
+        self.caldav = data.caldav;
+        self.caldav_synced = data.caldav_synced;
+        self.time_plan = data.slots.into_iter().map(|day| {
+            day.into_iter().map(|activity| {
+                ScheduledActivity { activity, ..ScheduledActivity::default() }
+            }).collect()
+        }).collect();
 
-fn get_cfg_file() -> String {
-    match std::env::var("HOME") {
-        Ok(path) => {
-            path + "/.config/plan"
+        if self.time_plan.is_empty() {
+            self.time_plan = new_time_plan(DEFAULT_DAYS, DEFAULT_BLOCKS);
         }
-        Err(_) => {
-            panic!("Failed to open home directory!");
+
+        let (_, blocks) = time_plan_size(&self.time_plan);
+        resize_block_times(&mut self.block_times, blocks);
+        self.grid_area.reset_time_inputs(&self.block_times);
+        self.activity_area.editing_activity = None;
+
+        if api_changed {
+            self.restart_api();
+        }
+    }
+
+    // Save the changed schedule and share it with the API and later launches
+    fn publish(&mut self) {
+        let data = self.to_data();
+        *self.snapshot.lock().unwrap() = data.clone();
+        self.autosaver.save(data);
+    }
+
+    // Serve the API with the current settings, or stop serving it
+    fn restart_api(&mut self) {
+        self.api_server = None;
+        if !self.api.enabled {
+            return;
+        }
+
+        *self.snapshot.lock().unwrap() = self.to_data();
+        match api::start(self.api.port, self.snapshot.clone(), Some(self.messages_tx.clone())) {
+            Ok(server) => self.api_server = Some(server),
+            Err(err) => self.error_banner = Some(ErrorBanner::new(err)),
+        }
+    }
+
+    fn start_sync(&mut self) {
+        self.sync_running = true;
+        self.last_sync = Some(std::time::Instant::now());
+        self.calendar_area.sync_status = Some(String::from("Synchronizing..."));
+
+        let mut data = self.to_data();
+        let edits = self.edits;
+        let outcomes = self.sync_tx.clone();
+        std::thread::spawn(move || {
+            let result = caldav::sync(&mut data);
+            outcomes.send(SyncOutcome { edits, data, result }).ok();
+        });
+    }
+
+    // Take the synchronized schedule, unless it was edited in the meantime. The calendar
+    // may be ahead of what was last agreed on then, which the next synchronization
+    // sorts out.
+    fn finish_sync(&mut self, outcome: SyncOutcome) {
+        self.sync_running = false;
+        self.calendar_area.sync_status = Some(match &outcome.result {
+            Ok(report) => {
+                let mut status = format!("Synchronized: {}", report.summary());
+                for conflict in report.conflicts.iter().chain(report.skipped.iter()) {
+                    status = format!("{}\n{}", status, conflict);
+                }
+                status
+            }
+            Err(err) => format!("Failed to synchronize: {}", err),
+        });
+
+        if outcome.edits != self.edits {
+            self.last_sync = None;
+            return;
+        }
+
+        // A failed synchronization may still have changed part of the plan
+        let changed_plan = outcome.result.as_ref().map(|report| { report.changed_plan() }).unwrap_or(true);
+        if changed_plan {
+            self.apply_data(outcome.data);
+        } else {
+            self.caldav_synced = outcome.data.caldav_synced;
+        }
+
+        self.publish();
+    }
+
+    // Commands of later launches are checked against the snapshot and applied through
+    // messages, like edits from the API
+    fn answer_requests(&self, lock: &mut instance::Instance) {
+        let snapshot = self.snapshot.clone();
+        let messages = self.messages_tx.clone();
+        lock.serve(move |request| {
+            let data = snapshot.lock().unwrap();
+            match request {
+                // iced can't raise its window, so point the user at it
+                instance::Request::Show => Ok(String::from("The schedule is already open in a window")),
+                instance::Request::JoinNow => {
+                    let activity = commands::current_activity(&data)?;
+                    messages.send(ScheduleMessage::LaunchMeeting(activity.id)).ok();
+                    Ok(String::new())
+                }
+                instance::Request::Sync => {
+                    messages.send(ScheduleMessage::SyncCalendar).ok();
+                    Ok(String::from("Synchronizing in the window"))
+                }
+                instance::Request::AddActivity(name, url) => {
+                    commands::check_new_activity(&data, &name)?;
+                    messages.send(ScheduleMessage::AddActivity(name.trim().to_string(), url.trim().to_string())).ok();
+                    Ok(String::new())
+                }
+                instance::Request::Assign(day, block, activity) => {
+                    let (day, block, activity) = commands::resolve_assignment(&data, &day, &block, &activity)?;
+                    messages.send(ScheduleMessage::ActivityChosen(day, block, activity)).ok();
+                    Ok(commands::describe_assignment(&data, day, block, activity))
+                }
+            }
+        });
+    }
+
+    // Notify of the reminder, or show it in the window if that's not possible
+    fn remind(&mut self, data: &PersistentData, reminder: trigger::Trigger) {
+        let (title, body) = reminder::describe(data, &reminder, &self.now);
+        let messages = self.messages_tx.clone();
+        let expires = reminder::expiry(&reminder, &self.now);
+        let result = self.notifier.remind(&title, &body, expires, Box::new(move |answer| {
+            messages.send(ScheduleMessage::ReminderAnswered(reminder, answer)).ok();
+        }));
+
+        if result.is_err() {
+            self.reminder_banner = Some(ReminderBanner::new(reminder, title, body));
         }
     }
 }
 
 impl Drop for Schedule {
     fn drop(&mut self) {
-        let mut data = PersistentData::default();
-        data.activities = self.activities.clone();
-        for (day_idx, day) in self.time_plan.iter_mut().enumerate() {
-            for (block_idx, block) in day.iter_mut().enumerate() {
-                data.plan[day_idx][block_idx] = block.activity.clone();
-            }
-        }
+        self.autosaver.save(self.to_data());
+        self.autosaver.flush();
+    }
+}
 
-        savefile::save_file(get_cfg_file().as_str(), 1, &data).unwrap();
+// "5 min ago" style age of a backup
+fn format_age(unix_time: u64) -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|time| { time.as_secs() })
+        .unwrap_or(0);
+
+    let minutes = now.saturating_sub(unix_time) / 60;
+    if minutes < 60 {
+        format!("{} min ago", minutes)
+    } else if minutes < 48 * 60 {
+        format!("{} h ago", minutes / 60)
+    } else {
+        format!("{} days ago", minutes / (24 * 60))
     }
 }
 
@@ -250,55 +1041,106 @@ fn find_activity(activities: &mut Vec<Activity>, activity_id: Option<usize>) ->
         .nth(0)
 }
 
-impl iced::Sandbox for Schedule {
+impl iced::Application for Schedule {
+    type Executor = iced::executor::Default;
     type Message = ScheduleMessage;
+    type Flags = Launch;
 
-    fn new() -> Schedule {
+    fn new(launch: Launch) -> (Schedule, iced::Command<ScheduleMessage>) {
+        let path = launch.path;
+        let (messages_tx, messages_rx) = std::sync::mpsc::channel();
+        let (sync_tx, sync_rx) = std::sync::mpsc::channel();
         let mut instance = Schedule {
             activity_area: ActivitiesArea::new(),
-            time_plan: TimePlan::default(),
+            grid_area: GridArea::new(),
+            settings_area: SettingsArea::new(),
+            calendar_area: CalendarArea::new(),
+            spreadsheet_area: SpreadsheetArea::new(),
+            import_area: ImportArea::new(),
+            load_failure: None,
+            error_banner: None,
+            time_plan: TimePlan::new(),
+            block_times: vec![],
+            backup_count: storage::DEFAULT_BACKUP_COUNT,
+            launcher: launcher::Launcher::default(),
+            table_scroll: iced::scrollable::State::new(),
+            side_scroll: iced::scrollable::State::new(),
             theme: style::Theme::Dark,
             activities: vec![],
+            next_activity_id: 0,
+            autosaver: autosave::Autosaver::new(path.clone()),
+            path: path.clone(),
+            now: clock::local_now(),
+            autojoin: autojoin::AutoJoinSettings::default(),
+            autojoiner: autojoin::AutoJoiner::new(),
+            cancel_autojoin_btn: iced::button::State::default(),
+            reminders: reminder::ReminderSettings::default(),
+            reminder_queue: reminder::Reminders::new(),
+            notifier: Box::new(notification::DesktopNotifier::new()),
+            reminder_banner: None,
+            messages_tx,
+            messages_rx,
+            api: api::ApiSettings::default(),
+            api_server: None,
+            snapshot: std::sync::Arc::new(std::sync::Mutex::new(PersistentData::new())),
+            caldav: caldav::CalDavSettings::default(),
+            caldav_synced: vec![],
+            sync_tx,
+            sync_rx,
+            sync_running: false,
+            last_sync: None,
+            edits: 0,
+            lock: None,
         };
 
-        if fs::metadata(get_cfg_file()).is_ok() {
-            let data = (savefile::load_file(get_cfg_file().as_str(), 1)
-                as Result<PersistentData, _>).unwrap();
+        let mut data = PersistentData::new();
+        if fs::metadata(&path).is_ok() {
+            match storage::load(path.as_str()) {
+                Ok(loaded) => {
+                    data = loaded;
+                }
+                Err(err) => {
+                    let mut message = format!("Failed to load {}: {}", path, err);
+                    if let Ok(moved) = storage::quarantine(&path) {
+                        message += &format!("\nThe unreadable file was moved to {}", moved.display());
+                    }
 
-            instance.activities = data.activities;
-            for (day_idx, day) in instance.time_plan.iter_mut().enumerate() {
-                for (block_idx, block) in day.iter_mut().enumerate() {
-                    block.activity = data.plan[day_idx][block_idx].clone();
+                    instance.load_failure = Some(LoadFailure::new(message, storage::backups(&path)));
                 }
             }
         }
 
-        instance
+        instance.apply_data(data);
+        instance.autosaver.flush_on_signal();
+        *instance.snapshot.lock().unwrap() = instance.to_data();
+        if let Some(mut lock) = launch.lock {
+            instance.answer_requests(&mut lock);
+            instance.lock = Some(lock);
+        }
+
+        (instance, iced::Command::none())
     }
 
     fn title(&self) -> String {
         return String::from("Class scheduler");
     }
 
-    fn update(&mut self, message: ScheduleMessage) {
+    fn subscription(&self) -> iced::Subscription<ScheduleMessage> {
+        iced::time::every(std::time::Duration::from_secs(1)).map(|_| ScheduleMessage::Tick)
+    }
+
+    fn update(&mut self, message: ScheduleMessage) -> iced::Command<ScheduleMessage> {
+        let changes_data = message.changes_data();
         let new_activity = &mut self.activity_area.new_activity;
         match message {
             ScheduleMessage::NewActivityRequest => {
                 assert_eq!(self.activity_area.editing_activity, None);
-                self.activities.push(Activity::default());
-
-                let mut taken = self.activities.iter()
-                    .map(|activity| {activity.id})
-                    .collect::<Vec<usize>>();
-                taken.sort();
-                let mex = taken.iter()
-                    .enumerate()
-                    .filter_map(|(idx, id)| {
-                        if *id == idx { None }
-                        else { Some(idx) } })
-                    .nth(0).unwrap_or(self.activities.len());
-
-                self.activities.last_mut().unwrap().id = mex;
+                self.activities.push(Activity {
+                    id: self.next_activity_id,
+                    ..Activity::default()
+                });
+                self.next_activity_id += 1;
+
                 self.activity_area.start_edit(self.activities.last().unwrap());
             }
 
@@ -311,9 +1153,30 @@ impl iced::Sandbox for Schedule {
                     NewActivityTextInputs::URL => {
                         new_activity.url = value;
                     }
+                    NewActivityTextInputs::Launcher => {
+                        new_activity.launcher_text = value;
+                    }
+                    NewActivityTextInputs::Profile => {
+                        new_activity.profile = value;
+                    }
+                    NewActivityTextInputs::AutoJoin => {
+                        new_activity.autojoin = value;
+                    }
                 }
             }
 
+            ScheduleMessage::NewActivityLauncherChosen(kind) => {
+                new_activity.launcher_kind = kind;
+            }
+
+            ScheduleMessage::NewActivityOpenInAppToggled(open_in_app) => {
+                new_activity.open_in_app = open_in_app;
+            }
+
+            ScheduleMessage::NewActivityMuteToggled(mute) => {
+                new_activity.mute_reminders = mute;
+            }
+
             ScheduleMessage::NewActivitySubmitted => {
                 assert_ne!(self.activity_area.editing_activity, None);
 
@@ -322,22 +1185,45 @@ impl iced::Sandbox for Schedule {
 
                 activity.name = new_activity.name.clone();
                 activity.url = new_activity.url.clone();
+                activity.launcher = new_activity.launcher_kind.map(|kind| {
+                    launcher::Launcher::with_kind(kind, new_activity.launcher_text.trim())
+                });
+                activity.profile = new_activity.profile.trim().to_string();
+                activity.open_in_app = new_activity.open_in_app;
+                // Anything but a number of minutes follows the global setting
+                activity.autojoin = new_activity.autojoin.trim().parse::<u32>().ok()
+                    .map(|minutes| { minutes.min(autojoin::MAX_MINUTES) });
+                activity.mute_reminders = new_activity.mute_reminders;
                 self.activity_area.editing_activity = None;
             }
 
             ScheduleMessage::ActivityChosen(day, block, idx) => {
-                if let Some(activity) = find_activity(&mut self.activities, idx) {
-                    self.time_plan[day][block].activity = Some(ActivityPickListItem {
-                        index: idx.unwrap(),
-                        label: activity.name.clone(),
-                    });
-                } else {
-                    self.time_plan[day][block].activity = None;
+                let activity = find_activity(&mut self.activities, idx).map(|activity| { activity.id });
+                // The API may refer to a slot which was just removed
+                if let Some(block) = self.time_plan.get_mut(day).and_then(|day| { day.get_mut(block) }) {
+                    block.activity = activity;
                 }
             }
 
-            ScheduleMessage::LaunchMeeting(url) => {
-                open::with(url.clone(), "google-chrome-unstable").ok();
+            ScheduleMessage::AddActivity(name, url) => {
+                self.activities.push(Activity {
+                    name,
+                    url,
+                    id: self.next_activity_id,
+                    ..Activity::default()
+                });
+                self.next_activity_id += 1;
+            }
+
+            ScheduleMessage::LaunchMeeting(id) => {
+                let result = match find_activity(&mut self.activities, Some(id)) {
+                    Some(activity) => launcher::launch_activity(&self.launcher, activity),
+                    None => Ok(()),
+                };
+
+                if let Err(err) = result {
+                    self.error_banner = Some(ErrorBanner::new(err));
+                }
             }
 
             ScheduleMessage::EditActivityRequest(idx) => {
@@ -352,27 +1238,385 @@ impl iced::Sandbox for Schedule {
             ScheduleMessage::RemoveActivity(remove_idx) => {
                 for day in self.time_plan.iter_mut() {
                     for block in day.iter_mut() {
-                        if block.activity.as_ref().map(|item| {item.index}) == Some(remove_idx) {
+                        if block.activity == Some(remove_idx) {
                             block.activity = None;
                         }
                     }
                 }
 
                 self.activities.retain(|activity| { activity.id != remove_idx });
+                if self.activity_area.editing_activity == Some(remove_idx) {
+                    self.activity_area.editing_activity = None;
+                }
+            }
+
+            ScheduleMessage::ResizePlan(days, blocks) => {
+                let days = days.max(1).min(CAPTIONS.len());
+                let blocks = blocks.max(1).min(MAX_BLOCKS);
+                resize_time_plan(&mut self.time_plan, days, blocks);
+                resize_block_times(&mut self.block_times, blocks);
+                self.grid_area.reset_time_inputs(&self.block_times);
+            }
+
+            ScheduleMessage::BackupCountChanged(count) => {
+                self.backup_count = count.min(storage::MAX_BACKUP_COUNT);
+            }
+
+            ScheduleMessage::LauncherKindChosen(kind) => {
+                if kind != self.launcher.kind() {
+                    let example = match kind {
+                        launcher::LauncherKind::SystemDefault => "",
+                        launcher::LauncherKind::Browser => "firefox",
+                        launcher::LauncherKind::Command => "firefox --new-window {url}",
+                    };
+
+                    self.launcher = launcher::Launcher::with_kind(kind, example);
+                }
+            }
+
+            ScheduleMessage::LauncherTextChanged(text) => {
+                self.launcher = launcher::Launcher::with_kind(self.launcher.kind(), &text);
+            }
+
+            ScheduleMessage::DismissError => {
+                self.error_banner = None;
+            }
+
+            ScheduleMessage::Tick => {
+                self.now = clock::local_now();
+                while let Ok(message) = self.messages_rx.try_recv() {
+                    self.update(message);
+                }
+
+                // The snapshot is refreshed whenever the schedule changes
+                let snapshot = self.snapshot.clone();
+                let data = snapshot.lock().unwrap();
+                for join in self.autojoiner.due(&data, &self.now) {
+                    self.update(ScheduleMessage::LaunchMeeting(join.activity));
+                }
+
+                for reminder in self.reminder_queue.due(&data, &self.now) {
+                    self.remind(&data, reminder);
+                }
+
+                drop(data);
+
+                while let Ok(outcome) = self.sync_rx.try_recv() {
+                    self.finish_sync(outcome);
+                }
+
+                let interval = std::time::Duration::from_secs(u64::from(self.caldav.minutes) * 60);
+                let sync_due = self.last_sync.map(|at| { at.elapsed() >= interval }).unwrap_or(true);
+                if self.caldav.enabled && !self.sync_running && sync_due {
+                    self.start_sync();
+                }
             }
+
+            ScheduleMessage::AutoJoinToggled(enabled) => {
+                self.autojoin.enabled = enabled;
+            }
+
+            ScheduleMessage::AutoJoinMinutesChanged(minutes) => {
+                self.autojoin.minutes = (minutes as u32).min(autojoin::MAX_MINUTES);
+            }
+
+            ScheduleMessage::RemindersToggled(enabled) => {
+                self.reminders.enabled = enabled;
+            }
+
+            ScheduleMessage::ReminderMinutesChanged(minutes) => {
+                self.reminders.minutes = (minutes as u32).min(reminder::MAX_MINUTES);
+            }
+
+            ScheduleMessage::ReminderAnswered(reminder, answer) => {
+                if self.reminder_banner.as_ref().map(|banner| { banner.reminder }) == Some(reminder) {
+                    self.reminder_banner = None;
+                }
+
+                match answer {
+                    reminder::Answer::Join => {
+                        self.update(ScheduleMessage::LaunchMeeting(reminder.activity));
+                    }
+                    reminder::Answer::Snooze => {
+                        self.reminder_queue.snooze(&reminder, &self.now);
+                    }
+                    reminder::Answer::Dismiss => {}
+                }
+            }
+
+            ScheduleMessage::ApiToggled(enabled) => {
+                self.api.enabled = enabled;
+                self.restart_api();
+            }
+
+            ScheduleMessage::ApiPortChanged(text) => {
+                self.settings_area.api_port = text;
+            }
+
+            ScheduleMessage::ApiPortSubmitted => {
+                match self.settings_area.api_port.trim().parse::<u16>() {
+                    Ok(port) if port != 0 => {
+                        self.api.port = port;
+                        self.restart_api();
+                    }
+                    _ => {
+                        self.error_banner = Some(ErrorBanner::new(
+                            format!("Invalid port '{}'", self.settings_area.api_port)));
+                    }
+                }
+            }
+
+            ScheduleMessage::CancelAutoJoin => {
+                if let Some(join) = self.autojoiner.countdown(&self.to_data(), &self.now) {
+                    self.autojoiner.cancel(&join);
+                }
+            }
+
+            ScheduleMessage::RestoreBackup(idx) => {
+                let backup = match &self.load_failure {
+                    Some(failure) => failure.backups[idx].1.clone(),
+                    None => return iced::Command::none(),
+                };
+
+                match storage::restore(self.path.as_str(), &backup) {
+                    Ok(data) => {
+                        self.apply_data(data);
+                        *self.snapshot.lock().unwrap() = self.to_data();
+                        self.load_failure = None;
+                    }
+                    Err(err) => {
+                        let failure = self.load_failure.as_mut().unwrap();
+                        failure.message = format!("Failed to restore {}: {}", backup.display(), err);
+                    }
+                }
+            }
+
+            ScheduleMessage::DismissLoadFailure => {
+                self.load_failure = None;
+            }
+
+            ScheduleMessage::CalendarPathChanged(path) => {
+                self.calendar_area.path = path;
+            }
+
+            ScheduleMessage::ExportCalendar => {
+                let path = self.calendar_area.path.clone();
+                let status = match ical::export_to_file(&self.to_data(), std::path::Path::new(&path)) {
+                    Ok(_) => format!("Exported to {}", path),
+                    Err(err) => format!("Failed to export to {}: {}", path, err),
+                };
+
+                self.calendar_area.status = Some(status);
+            }
+
+            ScheduleMessage::ImportCalendar => {
+                let path = self.calendar_area.path.clone();
+                match ical::parse_file(std::path::Path::new(&path)) {
+                    Ok((events, skipped)) => {
+                        self.import_area.preview = Some(import::preview(&self.to_data(), &events, skipped));
+                        self.import_area.status = None;
+                        self.calendar_area.status = None;
+                    }
+                    Err(err) => {
+                        self.calendar_area.status = Some(format!("Failed to import: {}", err));
+                    }
+                }
+            }
+
+            ScheduleMessage::CalDavTextChanged(field, text) => {
+                match field {
+                    CalDavField::Url => self.caldav.url = text,
+                    CalDavField::Username => self.caldav.username = text,
+                    CalDavField::Password => self.caldav.password = text,
+                }
+            }
+
+            ScheduleMessage::CalDavToggled(enabled) => {
+                self.caldav.enabled = enabled;
+            }
+
+            ScheduleMessage::CalDavMinutesChanged(minutes) => {
+                self.caldav.minutes = (minutes as u32).clamp(1, caldav::MAX_MINUTES);
+            }
+
+            ScheduleMessage::CalDavRemoteWinsToggled(remote_wins) => {
+                self.caldav.conflicts = if remote_wins { caldav::Winner::Remote } else { caldav::Winner::Local };
+            }
+
+            ScheduleMessage::SyncCalendar => {
+                if !self.sync_running {
+                    self.start_sync();
+                }
+            }
+
+            ScheduleMessage::CsvPathChanged(path) => {
+                self.spreadsheet_area.path = path;
+            }
+
+            ScheduleMessage::ExportCsv => {
+                let path = self.spreadsheet_area.path.clone();
+                let status = match csv_format::export_to_file(&self.to_data(), std::path::Path::new(&path)) {
+                    Ok(_) => format!("Exported to {}", path),
+                    Err(err) => format!("Failed to export to {}: {}", path, err),
+                };
+
+                self.spreadsheet_area.status = Some(status);
+            }
+
+            ScheduleMessage::LoadCsv => {
+                let path = self.spreadsheet_area.path.clone();
+                match csv_format::read_table(std::path::Path::new(&path)) {
+                    Ok(table) => {
+                        self.spreadsheet_area.mapping = csv_format::ColumnMapping::guess(&table.headers);
+                        self.spreadsheet_area.table = Some(table);
+                        self.spreadsheet_area.status = None;
+                    }
+                    Err(err) => {
+                        self.spreadsheet_area.table = None;
+                        self.spreadsheet_area.status = Some(format!("Failed to import: {}", err));
+                    }
+                }
+            }
+
+            ScheduleMessage::CsvColumnChosen(field, column) => {
+                self.spreadsheet_area.mapping.set(field, column);
+            }
+
+            ScheduleMessage::PreviewCsvImport => {
+                let csv_import = match &self.spreadsheet_area.table {
+                    Some(table) => csv_format::to_events(table, &self.spreadsheet_area.mapping),
+                    None => return iced::Command::none(),
+                };
+
+                match csv_import {
+                    Ok(csv_import) => {
+                        let data = self.to_data();
+                        let mut preview = import::preview(&data, &csv_import.events, csv_import.skipped);
+                        for (name, url) in csv_import.activities.iter() {
+                            preview.add_activity(&data, name, url);
+                        }
+
+                        self.import_area.preview = Some(preview);
+                        self.import_area.status = None;
+                        self.spreadsheet_area.table = None;
+                        self.spreadsheet_area.status = None;
+                    }
+                    Err(err) => {
+                        self.spreadsheet_area.status = Some(err);
+                    }
+                }
+            }
+
+            ScheduleMessage::CancelCsvImport => {
+                self.spreadsheet_area.table = None;
+            }
+
+            ScheduleMessage::ApplyImport(replace_conflicts) => {
+                if let Some(preview) = self.import_area.preview.take() {
+                    let mut data = self.to_data();
+                    let written = import::apply(&mut data, &preview, replace_conflicts);
+                    self.apply_data(data);
+                    self.import_area.status = Some(format!("Imported {} activities and {} slots",
+                                                           preview.new_activities.len(), written));
+                }
+            }
+
+            ScheduleMessage::CancelImport => {
+                self.import_area.preview = None;
+            }
+
+            ScheduleMessage::BlockTimeChanged(block, field, value) => {
+                let input = &mut self.grid_area.time_inputs[block];
+                match field {
+                    BlockTimeField::Start => {
+                        input.start = value;
+                    }
+                    BlockTimeField::End => {
+                        input.end = value;
+                    }
+                }
+
+                if let (Some(start), Some(end)) = (parse_clock(&input.start), parse_clock(&input.end)) {
+                    if start < end {
+                        self.block_times[block] = BlockTime { start, end };
+                    }
+                }
+            }
+        }
+
+        if changes_data {
+            self.edits += 1;
+            self.publish();
         }
+
+        iced::Command::none()
     }
 
     fn view(&mut self) -> iced::Element<ScheduleMessage> {
         let theme = self.theme;
+        let (days, blocks) = time_plan_size(&self.time_plan);
+
+        // Before the areas borrow their parts of the schedule
+        let data = self.clock_data();
+        let clock = clock_banner(&data, &self.now);
+        let countdown = self.autojoiner.countdown(&data, &self.now).map(|join| {
+            let name = activity_name(&data, Some(join.activity)).unwrap_or("?");
+            format!("Auto-join {} in {}s", name, join.seconds_left(&self.now))
+        });
+
+        let activities = self.activity_area.layout(theme, &mut self.activities, self.launcher.kind());
+        let grid = self.grid_area.layout(theme, days, blocks);
+        let settings = self.settings_area.layout(theme, self.backup_count, &self.launcher,
+                                                 &self.autojoin, &self.reminders, &self.api);
+        let calendar = self.calendar_area.layout(theme, &self.caldav, self.sync_running);
+        let spreadsheet = self.spreadsheet_area.layout(theme);
+        let import = self.import_area.layout(theme);
+        let table = time_plan_layout(&mut self.time_plan, &self.block_times,
+                                     &mut self.activities, theme, &self.now);
+
+        let mut content = iced::Column::new();
+        if let Some(failure) = &mut self.load_failure {
+            content = content.push(failure.layout(theme));
+        }
 
-        let activities = self.activity_area.layout(theme, &mut self.activities);
-        let table = time_plan_layout(&mut self.time_plan, &mut self.activities, theme);
+        if let Some(banner) = &mut self.error_banner {
+            content = content.push(banner.layout(theme));
+        }
 
-        let content = iced::Row::new()
-            .padding(20)
-            .push(table)
-            .push(activities);
+        if let Some(banner) = &mut self.reminder_banner {
+            content = content.push(banner.layout(theme));
+        }
+
+        if let Some(countdown) = countdown {
+            content = content.push(iced::Row::new()
+                                   .padding(20)
+                                   .spacing(10)
+                                   .push(iced::Text::new(countdown)
+                                         .vertical_alignment(iced::VerticalAlignment::Center)
+                                         .height(iced::Length::Units(30)))
+                                   .push(iced::Button::new(&mut self.cancel_autojoin_btn,
+                                                           iced::Text::new("Cancel"))
+                                         .on_press(ScheduleMessage::CancelAutoJoin)
+                                         .style(theme)));
+        }
+
+        let content = content
+            .push(iced::Container::new(iced::Text::new(clock).size(20))
+                  .padding(20)
+                  .width(iced::Length::Fill))
+            .push(iced::Row::new()
+                  .padding(20)
+                  .push(iced::Scrollable::new(&mut self.table_scroll)
+                        .push(table)
+                        .style(theme))
+                  .push(iced::Scrollable::new(&mut self.side_scroll)
+                        .push(activities)
+                        .push(grid)
+                        .push(calendar)
+                        .push(spreadsheet)
+                        .push(import)
+                        .push(settings)
+                        .style(theme)));
 
         iced::Container::new(content)
             .width(iced::Length::Fill)
@@ -383,8 +1627,8 @@ impl iced::Sandbox for Schedule {
 }
 
 impl ActivitiesArea {
-    fn layout<'a>(&'a mut self,
-              theme: style::Theme, activities: &mut Vec<Activity>) -> iced::Column<'a, ScheduleMessage> {
+    fn layout<'a>(&'a mut self, theme: style::Theme, activities: &mut Vec<Activity>,
+                  global_launcher: launcher::LauncherKind) -> iced::Column<'a, ScheduleMessage> {
         let mut content = iced::Column::new()
             .padding(20).align_items(iced::Align::Center);
 
@@ -425,7 +1669,7 @@ impl ActivitiesArea {
         content = content.push(iced::Space::with_height(iced::Length::Units(10)));
 
         if self.editing_activity != None {
-            content = content.push(self.new_activity.layout(theme));
+            content = content.push(self.new_activity.layout(theme, global_launcher));
         } else {
             let btn = iced::Button::new(&mut self.new_activity_btn,
                                         iced::Text::new("Add new activity"))
@@ -442,11 +1686,429 @@ impl ActivitiesArea {
         self.editing_activity = Some(activity.id);
         self.new_activity.name = activity.name.clone();
         self.new_activity.url = activity.url.clone();
+        self.new_activity.launcher_kind = activity.launcher.as_ref().map(|launcher| { launcher.kind() });
+        self.new_activity.launcher_text = activity.launcher.as_ref()
+            .map(|launcher| { launcher.text().to_string() })
+            .unwrap_or_default();
+        self.new_activity.profile = activity.profile.clone();
+        self.new_activity.open_in_app = activity.open_in_app;
+        self.new_activity.autojoin = activity.autojoin
+            .map(|minutes| { minutes.to_string() })
+            .unwrap_or_default();
+        self.new_activity.mute_reminders = activity.mute_reminders;
+    }
+}
+
+// "Label: value" row with -/+ buttons for numeric settings
+fn stepper_row<'a>(theme: style::Theme, label: &str, value: usize, range: (usize, usize),
+                   remove_state: &'a mut iced::button::State, add_state: &'a mut iced::button::State,
+                   on_change: impl Fn(usize) -> ScheduleMessage) -> iced::Row<'a, ScheduleMessage> {
+    let (min, max) = range;
+    let step_btn = |state, label, new_value: usize| {
+        let btn = iced::Button::new(state, iced::Text::new(label)
+                                    .horizontal_alignment(iced::HorizontalAlignment::Center))
+            .width(iced::Length::Units(30))
+            .height(iced::Length::Units(30));
+
+        if new_value != value && new_value >= min && new_value <= max {
+            btn.on_press(on_change(new_value)).style(theme)
+        } else {
+            btn.style(style::InactiveButton)
+        }
+    };
+
+    iced::Row::new()
+        .push(iced::Text::new(format!("{}: {}", label, value))
+              .vertical_alignment(iced::VerticalAlignment::Center)
+              .width(iced::Length::Units(100))
+              .height(iced::Length::Fill))
+        .push(step_btn(remove_state, "-", value.saturating_sub(1)))
+        .push(iced::Space::with_width(iced::Length::Units(10)))
+        .push(step_btn(add_state, "+", value + 1))
+        .height(iced::Length::Units(30))
+}
+
+impl GridArea {
+    fn layout<'a>(&'a mut self,
+              theme: style::Theme, days: usize, blocks: usize) -> iced::Column<'a, ScheduleMessage> {
+        let time_input = |state, block: usize, field: BlockTimeField, value| {
+            iced::TextInput::new(
+                state,
+                "HH:MM",
+                value,
+                move |new_value| ScheduleMessage::BlockTimeChanged(block, field, new_value))
+                .width(iced::Length::Units(70))
+                .style(theme)
+        };
+
+        let times = self.time_inputs.iter_mut()
+            .enumerate()
+            .fold(iced::Column::new().spacing(5), |column, (block, input)| {
+                column.push(iced::Row::new()
+                    .push(iced::Text::new(format!("{}.", block + 1))
+                          .width(iced::Length::Units(30)))
+                    .push(time_input(&mut input.start_state, block, BlockTimeField::Start, &input.start))
+                    .push(iced::Text::new("\u{2013}")
+                          .horizontal_alignment(iced::HorizontalAlignment::Center)
+                          .width(iced::Length::Units(20)))
+                    .push(time_input(&mut input.end_state, block, BlockTimeField::End, &input.end)))
+            });
+
+        iced::Column::new()
+            .padding(20)
+            .spacing(10)
+            .push(stepper_row(theme, "Days", days, (1, CAPTIONS.len()),
+                              &mut self.remove_day_btn, &mut self.add_day_btn,
+                              |days| ScheduleMessage::ResizePlan(days, blocks)))
+            .push(stepper_row(theme, "Blocks", blocks, (1, MAX_BLOCKS),
+                              &mut self.remove_block_btn, &mut self.add_block_btn,
+                              |blocks| ScheduleMessage::ResizePlan(days, blocks)))
+            .push(times)
+    }
+}
+
+impl SettingsArea {
+    fn layout<'a>(&'a mut self, theme: style::Theme, backup_count: usize, launcher: &launcher::Launcher,
+                  autojoin: &autojoin::AutoJoinSettings,
+                  reminders: &reminder::ReminderSettings,
+                  api: &api::ApiSettings) -> iced::Column<'a, ScheduleMessage> {
+        let launcher_row = iced::Row::new()
+            .spacing(10)
+            .push(iced::Text::new("Open meetings with")
+                  .vertical_alignment(iced::VerticalAlignment::Center)
+                  .height(iced::Length::Fill))
+            .push(iced::pick_list::PickList::new(
+                    &mut self.launcher_pick,
+                    &launcher::LauncherKind::ALL[..],
+                    Some(launcher.kind()),
+                    ScheduleMessage::LauncherKindChosen)
+                  .style(theme))
+            .height(iced::Length::Units(30));
+
+        let mut content = iced::Column::new()
+            .padding(20)
+            .spacing(10)
+            .push(stepper_row(theme, "Backups", backup_count, (0, storage::MAX_BACKUP_COUNT),
+                              &mut self.remove_backup_btn, &mut self.add_backup_btn,
+                              ScheduleMessage::BackupCountChanged))
+            .push(iced::Checkbox::new(autojoin.enabled, "Join all meetings automatically",
+                                      ScheduleMessage::AutoJoinToggled)
+                  .style(theme))
+            .push(stepper_row(theme, "Minutes early", autojoin.minutes as usize,
+                              (0, autojoin::MAX_MINUTES as usize),
+                              &mut self.fewer_minutes_btn, &mut self.more_minutes_btn,
+                              ScheduleMessage::AutoJoinMinutesChanged))
+            .push(iced::Checkbox::new(reminders.enabled, "Remind before classes",
+                                      ScheduleMessage::RemindersToggled)
+                  .style(theme))
+            .push(stepper_row(theme, "Remind early", reminders.minutes as usize,
+                              (0, reminder::MAX_MINUTES as usize),
+                              &mut self.fewer_reminder_minutes_btn, &mut self.more_reminder_minutes_btn,
+                              ScheduleMessage::ReminderMinutesChanged))
+            .push(iced::Checkbox::new(api.enabled, "Serve the schedule on localhost",
+                                      ScheduleMessage::ApiToggled)
+                  .style(theme))
+            .push(iced::Row::new()
+                  .spacing(10)
+                  .push(iced::Text::new("Port")
+                        .vertical_alignment(iced::VerticalAlignment::Center)
+                        .height(iced::Length::Fill))
+                  .push(iced::TextInput::new(&mut self.api_port_state, "Port", &self.api_port,
+                                             ScheduleMessage::ApiPortChanged)
+                        .on_submit(ScheduleMessage::ApiPortSubmitted)
+                        .width(iced::Length::Units(100))
+                        .style(theme))
+                  .height(iced::Length::Units(30)))
+            .push(launcher_row);
+
+        let placeholder = match launcher.kind() {
+            launcher::LauncherKind::SystemDefault => return content,
+            launcher::LauncherKind::Browser => "Browser, such as firefox",
+            launcher::LauncherKind::Command => "Command, {url} is replaced by the link",
+        };
+
+        content = content.push(iced::TextInput::new(&mut self.launcher_text_state, placeholder,
+                                                    launcher.text(), ScheduleMessage::LauncherTextChanged)
+                               .width(iced::Length::Units(400))
+                               .style(theme));
+        content
+    }
+}
+
+impl ErrorBanner {
+    fn layout<'a>(&'a mut self, theme: style::Theme) -> iced::Column<'a, ScheduleMessage> {
+        iced::Column::new()
+            .padding(20)
+            .push(iced::Row::new()
+                  .spacing(10)
+                  .push(iced::Text::new(self.message.clone())
+                        .color(iced::Color::from_rgb(0.9, 0.3, 0.3))
+                        .vertical_alignment(iced::VerticalAlignment::Center)
+                        .height(iced::Length::Fill))
+                  .push(iced::Button::new(&mut self.dismiss_btn, iced::Text::new("Dismiss"))
+                        .on_press(ScheduleMessage::DismissError)
+                        .style(theme))
+                  .height(iced::Length::Units(30)))
+    }
+}
+
+impl ReminderBanner {
+    fn layout<'a>(&'a mut self, theme: style::Theme) -> iced::Column<'a, ScheduleMessage> {
+        let reminder = self.reminder;
+        let answer = |answer| { ScheduleMessage::ReminderAnswered(reminder, answer) };
+
+        iced::Column::new()
+            .padding(20)
+            .spacing(10)
+            .push(iced::Text::new(self.title.clone()))
+            .push(iced::Text::new(self.body.clone())
+                  .size(16)
+                  .color(iced::Color::from_rgb(0.5, 0.5, 0.5)))
+            .push(iced::Row::new()
+                  .spacing(10)
+                  .push(iced::Button::new(&mut self.join_btn, iced::Text::new("Join"))
+                        .on_press(answer(reminder::Answer::Join))
+                        .style(theme))
+                  .push(iced::Button::new(&mut self.snooze_btn, iced::Text::new("Snooze"))
+                        .on_press(answer(reminder::Answer::Snooze))
+                        .style(theme))
+                  .push(iced::Button::new(&mut self.dismiss_btn, iced::Text::new("Dismiss"))
+                        .on_press(answer(reminder::Answer::Dismiss))
+                        .style(theme)))
+    }
+}
+
+impl CalendarArea {
+    fn layout<'a>(&'a mut self, theme: style::Theme, caldav: &caldav::CalDavSettings,
+                  sync_running: bool) -> iced::Column<'a, ScheduleMessage> {
+        let mut content = iced::Column::new()
+            .padding(20)
+            .spacing(10)
+            .push(iced::TextInput::new(&mut self.path_state, "Calendar file", &self.path,
+                                       ScheduleMessage::CalendarPathChanged)
+                  .width(iced::Length::Units(400))
+                  .style(theme))
+            .push(iced::Row::new()
+                  .spacing(10)
+                  .push(iced::Button::new(&mut self.export_btn, iced::Text::new("Export .ics"))
+                        .on_press(ScheduleMessage::ExportCalendar)
+                        .style(theme))
+                  .push(iced::Button::new(&mut self.import_btn, iced::Text::new("Import .ics"))
+                        .on_press(ScheduleMessage::ImportCalendar)
+                        .style(theme)));
+
+        if let Some(status) = &self.status {
+            content = content.push(iced::Text::new(status.clone()).size(16));
+        }
+
+        let caldav_input = |state, placeholder, value: &str, field| {
+            iced::TextInput::new(state, placeholder, value,
+                                 move |text| { ScheduleMessage::CalDavTextChanged(field, text) })
+                .width(iced::Length::Units(400))
+                .style(theme)
+        };
+
+        let mut sync_btn = iced::Button::new(&mut self.sync_btn, iced::Text::new("Sync now"));
+        if sync_running || caldav.url.trim().is_empty() {
+            sync_btn = sync_btn.style(style::InactiveButton);
+        } else {
+            sync_btn = sync_btn.on_press(ScheduleMessage::SyncCalendar).style(theme);
+        }
+
+        content = content
+            .push(caldav_input(&mut self.caldav_url_state, "CalDAV calendar URL", &caldav.url, CalDavField::Url))
+            .push(caldav_input(&mut self.caldav_username_state, "User name", &caldav.username,
+                               CalDavField::Username))
+            .push(caldav_input(&mut self.caldav_password_state, "Password", &caldav.password,
+                               CalDavField::Password).password())
+            .push(iced::Checkbox::new(caldav.enabled, "Synchronize automatically", ScheduleMessage::CalDavToggled)
+                  .style(theme))
+            .push(stepper_row(theme, "Every (min)", caldav.minutes as usize, (1, caldav::MAX_MINUTES as usize),
+                              &mut self.fewer_sync_minutes_btn, &mut self.more_sync_minutes_btn,
+                              ScheduleMessage::CalDavMinutesChanged))
+            .push(iced::Checkbox::new(caldav.conflicts == caldav::Winner::Remote,
+                                      "Keep the calendar's version on conflicts",
+                                      ScheduleMessage::CalDavRemoteWinsToggled)
+                  .style(theme))
+            .push(sync_btn);
+
+        if let Some(status) = &self.sync_status {
+            content = content.push(iced::Text::new(status.clone()).size(16));
+        }
+
+        content
+    }
+}
+
+impl SpreadsheetArea {
+    fn layout<'a>(&'a mut self, theme: style::Theme) -> iced::Column<'a, ScheduleMessage> {
+        let mut content = iced::Column::new()
+            .padding(20)
+            .spacing(10)
+            .push(iced::TextInput::new(&mut self.path_state, "CSV file", &self.path,
+                                       ScheduleMessage::CsvPathChanged)
+                  .width(iced::Length::Units(400))
+                  .style(theme))
+            .push(iced::Row::new()
+                  .spacing(10)
+                  .push(iced::Button::new(&mut self.export_btn, iced::Text::new("Export .csv"))
+                        .on_press(ScheduleMessage::ExportCsv)
+                        .style(theme))
+                  .push(iced::Button::new(&mut self.import_btn, iced::Text::new("Import .csv"))
+                        .on_press(ScheduleMessage::LoadCsv)
+                        .style(theme)));
+
+        if let Some(status) = &self.status {
+            content = content.push(iced::Text::new(status.clone()).size(16));
+        }
+
+        let table = match &self.table {
+            Some(table) => table,
+            None => return content,
+        };
+
+        let mut columns = vec![ColumnPickListItem { column: None, label: String::from("(none)") }];
+        columns.extend(table.headers.iter().enumerate().map(|(column, header)| {
+            ColumnPickListItem { column: Some(column), label: header.clone() }
+        }));
+
+        content = content.push(iced::Text::new(format!("{} rows, choose the column of each field:",
+                                                       table.rows.len())).size(16));
+
+        let mapping = &self.mapping;
+        for (field, state) in csv_format::CsvField::ALL.iter().zip(self.column_picks.iter_mut()) {
+            let field = *field;
+            let selected = columns.iter().find(|item| { item.column == mapping.get(field) }).cloned();
+            content = content.push(iced::Row::new()
+                .push(iced::Text::new(field.label())
+                      .vertical_alignment(iced::VerticalAlignment::Center)
+                      .width(iced::Length::Units(100))
+                      .height(iced::Length::Fill))
+                .push(iced::pick_list::PickList::new(
+                        state,
+                        columns.clone(),
+                        selected,
+                        move |item| { ScheduleMessage::CsvColumnChosen(field, item.column) })
+                      .width(iced::Length::Units(200))
+                      .style(theme))
+                .height(iced::Length::Units(30)));
+        }
+
+        content.push(iced::Row::new()
+            .spacing(10)
+            .push(iced::Button::new(&mut self.preview_btn, iced::Text::new("Preview import"))
+                  .on_press(ScheduleMessage::PreviewCsvImport)
+                  .style(theme))
+            .push(iced::Button::new(&mut self.cancel_btn, iced::Text::new("Cancel"))
+                  .on_press(ScheduleMessage::CancelCsvImport)
+                  .style(theme)))
+    }
+}
+
+impl ImportArea {
+    fn layout<'a>(&'a mut self, theme: style::Theme) -> iced::Column<'a, ScheduleMessage> {
+        let mut content = iced::Column::new()
+            .padding(20)
+            .spacing(10);
+
+        let preview = match &self.preview {
+            Some(preview) => preview,
+            None => {
+                if let Some(status) = &self.status {
+                    content = content.push(iced::Text::new(status.clone()).size(16));
+                }
+                return content;
+            }
+        };
+
+        let mut buttons = iced::Row::new()
+            .spacing(10)
+            .push(iced::Button::new(&mut self.apply_keep_btn, iced::Text::new("Apply"))
+                  .on_press(ScheduleMessage::ApplyImport(false))
+                  .style(theme));
+
+        if preview.conflicts() > 0 {
+            buttons = buttons.push(iced::Button::new(&mut self.apply_replace_btn,
+                                                     iced::Text::new("Apply and replace conflicts"))
+                                   .on_press(ScheduleMessage::ApplyImport(true))
+                                   .style(theme));
+        }
+
+        content
+            .push(iced::Text::new("Import preview"))
+            .push(import_preview_layout(preview))
+            .push(buttons
+                  .push(iced::Button::new(&mut self.cancel_btn, iced::Text::new("Cancel"))
+                        .on_press(ScheduleMessage::CancelImport)
+                        .style(theme)))
+    }
+}
+
+// Summary of an import before it is applied
+fn import_preview_layout<'a>(preview: &import::ImportPreview) -> iced::Column<'a, ScheduleMessage> {
+    let line = |text: String| { iced::Text::new(text).size(16) };
+    let warning = iced::Color::from_rgb(0.9, 0.3, 0.3);
+
+    let mut content = iced::Column::new().spacing(5);
+    if !preview.new_activities.is_empty() {
+        let names: Vec<&str> = preview.new_activities.iter().map(|(name, _)| { name.as_str() }).collect();
+        content = content.push(line(format!("New activities: {}", names.join(", "))));
+    }
+
+    if preview.assignments.is_empty() {
+        content = content.push(line(String::from("No slots to fill")));
+    }
+
+    for assignment in preview.assignments.iter() {
+        let slot = format!("{} block {}: {}", CAPTIONS[assignment.day], assignment.block + 1,
+                           assignment.summary);
+        content = content.push(match &assignment.conflict {
+            Some(current) => line(format!("{} (conflicts with {})", slot, current)).color(warning),
+            None => line(slot),
+        });
+    }
+
+    for unmatched in preview.unmatched.iter() {
+        content = content.push(line(format!("Skipped: {}", unmatched))
+                               .color(iced::Color::from_rgb(0.6, 0.6, 0.6)));
+    }
+
+    content
+}
+
+impl LoadFailure {
+    fn layout<'a>(&'a mut self, theme: style::Theme) -> iced::Column<'a, ScheduleMessage> {
+        let content = iced::Column::new()
+            .padding(20)
+            .spacing(10)
+            .push(iced::Text::new(self.message.clone())
+                  .color(iced::Color::from_rgb(0.9, 0.3, 0.3)));
+
+        let content = self.backups.iter()
+            .zip(self.restore_btns.iter_mut())
+            .enumerate()
+            .fold(content, |content, (idx, ((time, path), btn))| {
+                content.push(iced::Row::new()
+                    .push(iced::Button::new(btn, iced::Text::new("Restore"))
+                          .on_press(ScheduleMessage::RestoreBackup(idx))
+                          .style(theme))
+                    .push(iced::Space::with_width(iced::Length::Units(10)))
+                    .push(iced::Text::new(format!("{} ({})", path.display(), format_age(*time)))
+                          .vertical_alignment(iced::VerticalAlignment::Center)
+                          .height(iced::Length::Fill))
+                    .height(iced::Length::Units(30)))
+            });
+
+        content.push(iced::Button::new(&mut self.dismiss_btn,
+                                       iced::Text::new("Start with an empty schedule"))
+                     .on_press(ScheduleMessage::DismissLoadFailure)
+                     .style(theme))
     }
 }
 
 impl ActivityCreateParams {
-    fn layout(&mut self, theme: style::Theme) -> iced::Column<ScheduleMessage> {
+    fn layout(&mut self, theme: style::Theme,
+              global_launcher: launcher::LauncherKind) -> iced::Column<ScheduleMessage> {
         let new_label = |state, msg: NewActivityTextInputs, value| {
             iced::TextInput::new(
                 state,
@@ -456,11 +2118,61 @@ impl ActivityCreateParams {
                 .style(theme)
         };
 
-        iced::Column::new()
+        let launcher_items: Vec<ActivityLauncherItem> = std::iter::once(None)
+            .chain(launcher::LauncherKind::ALL.iter().cloned().map(Some))
+            .map(|kind| { ActivityLauncherItem { kind } })
+            .collect();
+
+        let mut content = iced::Column::new()
             .spacing(20)
             .align_items(iced::Align::Start)
             .push(new_label(&mut self.name_state, NewActivityTextInputs::Name, &self.name))
             .push(new_label(&mut self.url_state, NewActivityTextInputs::URL, &self.url))
+            .push(iced::Row::new()
+                  .spacing(10)
+                  .push(iced::Text::new("Open with")
+                        .vertical_alignment(iced::VerticalAlignment::Center)
+                        .height(iced::Length::Fill))
+                  .push(iced::pick_list::PickList::new(
+                          &mut self.launcher_pick,
+                          launcher_items,
+                          Some(ActivityLauncherItem { kind: self.launcher_kind }),
+                          |item| { ScheduleMessage::NewActivityLauncherChosen(item.kind) })
+                        .style(theme))
+                  .height(iced::Length::Units(30)));
+
+        if let Some(link) = meeting::parse(&self.url) {
+            content = content.push(iced::Text::new(link.summary())
+                                   .size(16)
+                                   .color(iced::Color::from_rgb(0.5, 0.5, 0.5)));
+
+            if link.app_url.is_some() {
+                content = content.push(iced::Checkbox::new(self.open_in_app,
+                                                           format!("Open in the {} app", link.provider),
+                                                           ScheduleMessage::NewActivityOpenInAppToggled)
+                                       .style(theme));
+            }
+        }
+
+        let kind = self.launcher_kind.unwrap_or(global_launcher);
+        if self.launcher_kind.is_some() && kind != launcher::LauncherKind::SystemDefault {
+            content = content.push(new_label(&mut self.launcher_text_state,
+                                             NewActivityTextInputs::Launcher, &self.launcher_text));
+        }
+
+        // Profiles are passed to browsers, and to commands through {profile}
+        if kind != launcher::LauncherKind::SystemDefault {
+            content = content.push(new_label(&mut self.profile_state,
+                                             NewActivityTextInputs::Profile, &self.profile));
+        }
+
+        content = content
+            .push(new_label(&mut self.autojoin_state, NewActivityTextInputs::AutoJoin, &self.autojoin))
+            .push(iced::Checkbox::new(self.mute_reminders, "Mute reminders",
+                                      ScheduleMessage::NewActivityMuteToggled)
+                  .style(theme));
+
+        content
             .push(iced::Row::new()
                   .push(iced::Button::new(&mut self.new_activity_submit_btn,
                                           iced::Text::new("Submit"))
@@ -475,9 +2187,97 @@ impl ActivityCreateParams {
 }
 
 pub fn main() {
-    use iced::Sandbox;
+    use iced::Application;
+
+    let options = match cli::parse(std::env::args().skip(1)) {
+        Ok(options) => options,
+        Err(err) => {
+            eprintln!("{}\n\n{}", err, cli::USAGE);
+            std::process::exit(2);
+        }
+    };
+
+    if options.help {
+        println!("{}", cli::USAGE);
+        return;
+    }
+
+    let explicit = options.config.is_some() || std::env::var_os(paths::CONFIG_ENV).is_some();
+    let path = options.config.or_else(paths::schedule_file);
+    if let Some(path) = &path {
+        if !explicit {
+            paths::migrate_legacy_file(path);
+        }
+    }
+
+    if let Some(command) = options.command {
+        // Edits go through the process which has the schedule open, if there is one
+        let running = path.as_deref().and_then(instance::running);
+        let result = match (instance::Request::from_command(&command), running) {
+            (Some(request), Some(socket)) => {
+                instance::send(&socket, &request).map(|message| {
+                    if !message.is_empty() {
+                        println!("{}", message);
+                    }
+                })
+            }
+            _ => commands::run(command, path.as_deref()),
+        };
+
+        if let Err(err) = result {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+
+        return;
+    }
+
+    let path = match path {
+        Some(path) => path,
+        None => {
+            eprintln!("{}", paths::missing_schedule_error());
+            std::process::exit(1);
+        }
+    };
+
+    // Without a lock, for example when the runtime directory can't be created, run
+    // anyway like before there was one
+    let lock = match instance::start(&path) {
+        Ok(instance::Start::First(lock)) => Some(lock),
+        Ok(instance::Start::Running(socket)) => {
+            if options.daemon {
+                eprintln!("timeplan is already running for {}", path.display());
+                std::process::exit(1);
+            }
+
+            match instance::send(&socket, &instance::Request::Show) {
+                Ok(message) => println!("{}", message),
+                Err(err) => {
+                    eprintln!("{}", err);
+                    std::process::exit(1);
+                }
+            }
+
+            return;
+        }
+        Err(err) => {
+            eprintln!("{}", err);
+            None
+        }
+    };
+
+    if options.daemon {
+        if let Err(err) = daemon::run(&path, lock) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+        }
+
+        return;
+    }
+
+    let path = path.to_string_lossy().into_owned();
 
-    let mut stgs = iced::Settings::default();
+    let mut stgs = iced::Settings::with_flags(Launch { path, lock });
     stgs.window.size = (1300, 906);
     match Schedule::run(stgs) {
         Ok(_) => {}
@@ -0,0 +1,212 @@
+// Recognizes meeting links of common providers, to show their meeting ID and
+// passcode and to open them in the provider's desktop app instead of a browser.
+
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Provider {
+    Zoom,
+    Teams,
+    Jitsi,
+    Meet,
+}
+
+impl fmt::Display for Provider {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Provider::Zoom => write!(f, "Zoom"),
+            Provider::Teams => write!(f, "Teams"),
+            Provider::Jitsi => write!(f, "Jitsi"),
+            Provider::Meet => write!(f, "Meet"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MeetingLink {
+    pub provider: Provider,
+    pub id: Option<String>,
+    pub passcode: Option<String>,
+    // URI handled by the desktop app, None if the provider has no app
+    pub app_url: Option<String>,
+}
+
+impl MeetingLink {
+    // Short description for the plan, such as "Zoom 12345678901, passcode abc"
+    pub fn summary(&self) -> String {
+        let mut summary = self.provider.to_string();
+        if let Some(id) = &self.id {
+            summary = format!("{} {}", summary, id);
+        }
+        if let Some(passcode) = &self.passcode {
+            summary = format!("{}, passcode {}", summary, passcode);
+        }
+
+        summary
+    }
+}
+
+fn query_value(url: &url::Url, key: &str) -> Option<String> {
+    url.query_pairs()
+        .find(|(name, _)| { name == key })
+        .map(|(_, value)| { value.into_owned() })
+        .filter(|value| { !value.is_empty() })
+}
+
+fn path_segments(url: &url::Url) -> Vec<String> {
+    url.path_segments()
+        .map(|segments| { segments.filter(|segment| { !segment.is_empty() }).map(String::from).collect() })
+        .unwrap_or_default()
+}
+
+fn host_matches(host: &str, domain: &str) -> bool {
+    host == domain || host.ends_with(&format!(".{}", domain))
+}
+
+// https://zoom.us/j/123?pwd=abc, also on company subdomains such as us02web.zoom.us
+fn parse_zoom(url: &url::Url) -> Option<MeetingLink> {
+    let segments = path_segments(url);
+    let id = match segments.as_slice() {
+        [kind, id, ..] if kind == "j" || kind == "w" || kind == "s" => id.clone(),
+        _ => query_value(url, "confno")?,
+    };
+
+    if id.is_empty() || !id.chars().all(|c| { c.is_ascii_digit() }) {
+        return None;
+    }
+
+    let passcode = query_value(url, "pwd");
+    let mut params = vec![("action", "join"), ("confno", id.as_str())];
+    if let Some(passcode) = &passcode {
+        params.push(("pwd", passcode.as_str()));
+    }
+
+    let app_url = url::Url::parse_with_params("zoommtg://zoom.us/join", &params).ok()?;
+    Some(MeetingLink {
+        provider: Provider::Zoom,
+        app_url: Some(app_url.to_string()),
+        id: Some(id),
+        passcode,
+    })
+}
+
+// https://teams.microsoft.com/l/meetup-join/... or https://teams.microsoft.com/meet/123?p=abc
+fn parse_teams(url: &url::Url) -> Option<MeetingLink> {
+    let segments = path_segments(url);
+    let id = match segments.as_slice() {
+        [meet, id, ..] if meet == "meet" => Some(id.clone()),
+        [l, ..] if l == "l" => None,
+        _ => return None,
+    };
+
+    // The app takes the same path and query after its own scheme
+    let mut app_url = format!("msteams:{}", url.path());
+    if let Some(query) = url.query() {
+        app_url = format!("{}?{}", app_url, query);
+    }
+
+    Some(MeetingLink {
+        provider: Provider::Teams,
+        id,
+        passcode: query_value(url, "p"),
+        app_url: Some(app_url),
+    })
+}
+
+// https://meet.jit.si/SomeRoom, or a self-hosted server with jitsi in its name
+fn parse_jitsi(url: &url::Url) -> Option<MeetingLink> {
+    let room = path_segments(url).into_iter().next()?;
+    let host = url.host_str()?;
+
+    Some(MeetingLink {
+        provider: Provider::Jitsi,
+        app_url: Some(format!("jitsi-meet://{}/{}", host, room)),
+        id: Some(room),
+        passcode: None,
+    })
+}
+
+// https://meet.google.com/abc-defg-hij, which has no desktop app
+fn parse_meet(url: &url::Url) -> Option<MeetingLink> {
+    let code = path_segments(url).into_iter().next()?;
+    if code == "new" || code == "landing" {
+        return None;
+    }
+
+    Some(MeetingLink {
+        provider: Provider::Meet,
+        id: Some(code),
+        passcode: None,
+        app_url: None,
+    })
+}
+
+pub fn parse(link: &str) -> Option<MeetingLink> {
+    let url = url::Url::parse(link.trim()).ok()?;
+    if url.scheme() != "https" && url.scheme() != "http" {
+        return None;
+    }
+
+    let host = url.host_str()?.to_lowercase();
+    if host_matches(&host, "zoom.us") {
+        parse_zoom(&url)
+    } else if host_matches(&host, "teams.microsoft.com") || host_matches(&host, "teams.live.com") {
+        parse_teams(&url)
+    } else if host == "meet.google.com" {
+        parse_meet(&url)
+    } else if host == "meet.jit.si" || host.contains("jitsi") {
+        parse_jitsi(&url)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(provider: Provider, id: Option<&str>, passcode: Option<&str>,
+            app_url: Option<&str>) -> Option<MeetingLink> {
+        Some(MeetingLink {
+            provider,
+            id: id.map(String::from),
+            passcode: passcode.map(String::from),
+            app_url: app_url.map(String::from),
+        })
+    }
+
+    #[test]
+    fn parses_links() {
+        let cases = vec![
+            ("https://zoom.us/j/123?pwd=abc",
+             link(Provider::Zoom, Some("123"), Some("abc"),
+                  Some("zoommtg://zoom.us/join?action=join&confno=123&pwd=abc"))),
+            ("https://us02web.zoom.us/j/98765432101",
+             link(Provider::Zoom, Some("98765432101"), None,
+                  Some("zoommtg://zoom.us/join?action=join&confno=98765432101"))),
+            ("https://zoom.us/join?confno=42",
+             link(Provider::Zoom, Some("42"), None, Some("zoommtg://zoom.us/join?action=join&confno=42"))),
+            ("https://teams.microsoft.com/meet/123?p=xyz",
+             link(Provider::Teams, Some("123"), Some("xyz"), Some("msteams:/meet/123?p=xyz"))),
+            ("https://teams.microsoft.com/l/meetup-join/19%3ameeting/0?context=x",
+             link(Provider::Teams, None, None, Some("msteams:/l/meetup-join/19%3ameeting/0?context=x"))),
+            ("https://meet.jit.si/Physics101",
+             link(Provider::Jitsi, Some("Physics101"), None, Some("jitsi-meet://meet.jit.si/Physics101"))),
+            ("https://jitsi.example.org/Room",
+             link(Provider::Jitsi, Some("Room"), None, Some("jitsi-meet://jitsi.example.org/Room"))),
+            (" https://meet.google.com/abc-defg-hij ", link(Provider::Meet, Some("abc-defg-hij"), None, None)),
+            ("https://zoom.us/j/abc", None),
+            ("https://zoom.us/profile", None),
+            ("https://teams.microsoft.com/", None),
+            ("https://meet.google.com/new", None),
+            ("https://meet.jit.si/", None),
+            ("https://example.com/j/123", None),
+            ("ftp://zoom.us/j/123", None),
+            ("not a link", None),
+        ];
+
+        for (text, expected) in cases {
+            assert_eq!(parse(text), expected, "{}", text);
+        }
+    }
+}
@@ -0,0 +1,150 @@
+// Desktop notifications through the freedesktop notification service on the D-Bus
+// session bus, which is found through $DBUS_SESSION_BUS_ADDRESS.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use crate::reminder::Answer;
+
+const APP_NAME: &str = "timeplan";
+
+// Threads waiting for the answer to a notification. Servers which ignore the expiry
+// keep them waiting until the user closes the notification, so beyond this many
+// reminders are shown without actions.
+const MAX_WAITING: usize = 8;
+
+pub trait Notifier {
+    // Show a reminder with Join and Snooze actions, which goes away after `expires`.
+    // The answer is passed to `on_answer` from a background thread once the
+    // notification is clicked, closed or expires. Fails if there is no notification
+    // service to show it.
+    fn remind(&self, title: &str, body: &str, expires: Duration,
+              on_answer: Box<dyn FnOnce(Answer) + Send>) -> Result<(), String>;
+}
+
+// Counts one waiting thread while alive
+struct Waiting(Arc<AtomicUsize>);
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct DesktopNotifier {
+    waiting: Arc<AtomicUsize>,
+}
+
+impl DesktopNotifier {
+    pub fn new() -> DesktopNotifier {
+        DesktopNotifier::default()
+    }
+
+    // Number of notifications whose answer is still awaited
+    pub fn waiting(&self) -> usize {
+        self.waiting.load(Ordering::SeqCst)
+    }
+
+    fn start_waiting(&self) -> Option<Waiting> {
+        if self.waiting.fetch_add(1, Ordering::SeqCst) < MAX_WAITING {
+            Some(Waiting(self.waiting.clone()))
+        } else {
+            self.waiting.fetch_sub(1, Ordering::SeqCst);
+            None
+        }
+    }
+}
+
+impl Notifier for DesktopNotifier {
+    fn remind(&self, title: &str, body: &str, expires: Duration,
+              on_answer: Box<dyn FnOnce(Answer) + Send>) -> Result<(), String> {
+        let waiting = self.start_waiting();
+        let milliseconds = expires.as_millis().clamp(1, u128::from(u32::MAX)) as u32;
+
+        let mut notification = notify_rust::Notification::new();
+        notification.appname(APP_NAME)
+            .summary(title)
+            .body(body)
+            .timeout(notify_rust::Timeout::Milliseconds(milliseconds));
+        if waiting.is_some() {
+            notification.action("join", "Join").action("snooze", "Snooze");
+        }
+
+        let handle = notification.show()
+            .map_err(|err| { format!("Failed to show a notification: {}", err) })?;
+
+        let waiting = match waiting {
+            Some(waiting) => waiting,
+            None => return Ok(()),
+        };
+
+        thread::spawn(move || {
+            handle.wait_for_action(|action| {
+                on_answer(match action {
+                    "join" => Answer::Join,
+                    "snooze" => Answer::Snooze,
+                    _ => Answer::Dismiss,
+                })
+            });
+            drop(waiting);
+        });
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader};
+    use std::process::{Child, Command, Stdio};
+
+    // Private session bus without any services, stopped when dropped
+    struct Bus(Child);
+
+    impl Bus {
+        fn start() -> (Bus, String) {
+            let config = std::env::temp_dir().join(format!("timeplan-test-bus-{}.conf", std::process::id()));
+            // The policy of the standard session bus, without its service directories
+            std::fs::write(&config, "<busconfig><type>session</type><listen>unix:tmpdir=/tmp</listen>\
+                                     <policy context=\"default\">\
+                                     <allow send_destination=\"*\" eavesdrop=\"true\"/>\
+                                     <allow eavesdrop=\"true\"/><allow own=\"*\"/>\
+                                     </policy></busconfig>").unwrap();
+
+            let mut child = Command::new("dbus-daemon")
+                .arg(format!("--config-file={}", config.display()))
+                .args(["--nofork", "--print-address"])
+                .stdout(Stdio::piped())
+                .spawn()
+                .expect("dbus-daemon is needed for this test");
+
+            let mut address = String::new();
+            BufReader::new(child.stdout.as_mut().unwrap()).read_line(&mut address).unwrap();
+            (Bus(child), address.trim().to_string())
+        }
+    }
+
+    impl Drop for Bus {
+        fn drop(&mut self) {
+            self.0.kill().ok();
+            self.0.wait().ok();
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn fails_without_a_notification_service() {
+        let (_bus, address) = Bus::start();
+        std::env::set_var("DBUS_SESSION_BUS_ADDRESS", &address);
+
+        let notifier = DesktopNotifier::new();
+        let result = notifier.remind("Math in 5 min", "10:00\u{2013}11:30", Duration::from_secs(60),
+                                     Box::new(|_| { panic!("nothing to answer") }));
+        assert!(result.is_err());
+        assert_eq!(notifier.waiting(), 0);
+    }
+}
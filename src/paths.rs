@@ -0,0 +1,154 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+// Overrides the location of the schedule file, also set from --config
+pub const CONFIG_ENV: &str = "TIMEPLAN_CONFIG";
+
+const APP_DIR: &str = "timeplan";
+const SCHEDULE_FILE: &str = "plan";
+
+fn home_dir() -> Option<PathBuf> {
+    env::var_os("HOME")
+        .filter(|home| { !home.is_empty() })
+        .map(PathBuf::from)
+}
+
+// $var if it is an absolute path, as required by the XDG spec, otherwise $HOME/fallback
+fn xdg_dir(var: &str, fallback: &str) -> Option<PathBuf> {
+    match env::var_os(var).map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => Some(dir),
+        _ => home_dir().map(|home| { home.join(fallback) }),
+    }
+}
+
+pub fn missing_schedule_error() -> String {
+    format!("Cannot find a config directory, set $HOME, $XDG_CONFIG_HOME, ${} or pass --config",
+            CONFIG_ENV)
+}
+
+pub fn config_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config").map(|dir| { dir.join(APP_DIR) })
+}
+
+pub fn data_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_DATA_HOME", ".local/share").map(|dir| { dir.join(APP_DIR) })
+}
+
+// Lock files and sockets, which only matter while running. Without $XDG_RUNTIME_DIR
+// the temporary directory is used, with the user name to keep users apart.
+pub fn runtime_dir() -> Result<PathBuf, String> {
+    match env::var_os("XDG_RUNTIME_DIR").map(PathBuf::from) {
+        Some(dir) if dir.is_absolute() => Ok(dir.join(APP_DIR)),
+        _ => {
+            let user = env::var("USER").unwrap_or_else(|_| { String::from("user") });
+            let dir = env::temp_dir().join(format!("{}-{}", APP_DIR, user));
+            private_dir(&dir)?;
+            Ok(dir)
+        }
+    }
+}
+
+// Anyone can create a directory of a predictable name in the temporary directory, and
+// would then control the sockets of this user, so it is only used if it belongs to the
+// user. Earlier versions created it with the default mode, which is tightened.
+fn private_dir(dir: &Path) -> Result<(), String> {
+    match fs::DirBuilder::new().mode(0o700).create(dir) {
+        Ok(_) => {}
+        Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {}
+        Err(err) => return Err(format!("Failed to create {}: {}", dir.display(), err)),
+    }
+
+    // A link could lead anywhere, so it isn't followed
+    let metadata = fs::symlink_metadata(dir).map_err(|err| { format!("Failed to read {}: {}", dir.display(), err) })?;
+    if !metadata.is_dir() || metadata.uid() != unsafe { libc::getuid() } {
+        return Err(format!("{} is not a directory of this user, remove it or set $XDG_RUNTIME_DIR",
+                           dir.display()));
+    }
+
+    if metadata.mode() & 0o077 != 0 {
+        fs::set_permissions(dir, fs::Permissions::from_mode(0o700))
+            .map_err(|err| { format!("Failed to restrict {}: {}", dir.display(), err) })?;
+    }
+
+    Ok(())
+}
+
+pub fn default_schedule_file() -> Option<PathBuf> {
+    config_dir().map(|dir| { dir.join(SCHEDULE_FILE) })
+}
+
+// $TIMEPLAN_CONFIG, or the default location
+pub fn schedule_file() -> Option<PathBuf> {
+    env::var_os(CONFIG_ENV)
+        .filter(|path| { !path.is_empty() })
+        .map(PathBuf::from)
+        .or_else(default_schedule_file)
+}
+
+// Backups are kept next to the schedule, where they are easy to find
+pub fn backup_dir(schedule: &Path) -> PathBuf {
+    match schedule.parent() {
+        Some(dir) if dir != Path::new("") => dir.to_path_buf(),
+        _ => PathBuf::from("."),
+    }
+}
+
+// Versions before XDG support kept the schedule in $HOME/.config/plan
+pub fn migrate_legacy_file(schedule: &Path) {
+    let legacy = match home_dir() {
+        Some(home) => home.join(".config").join(SCHEDULE_FILE),
+        None => return,
+    };
+
+    if schedule.exists() || !legacy.is_file() || legacy == schedule {
+        return;
+    }
+
+    let result = schedule.parent()
+        .map(|dir| { fs::create_dir_all(dir) })
+        .unwrap_or(Ok(()))
+        .and_then(|_| { fs::rename(&legacy, schedule) });
+
+    match result {
+        Ok(_) => {
+            eprintln!("Moved {} to {}", legacy.display(), schedule.display());
+        }
+        Err(err) => {
+            eprintln!("Failed to move {} to {}: {}", legacy.display(), schedule.display(), err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_the_runtime_dir_private() {
+        let base = env::temp_dir().join(format!("timeplan-test-paths-{}", std::process::id()));
+        fs::create_dir_all(&base).unwrap();
+        let mode = |dir: &Path| { fs::metadata(dir).unwrap().mode() & 0o777 };
+
+        let dir = base.join("new");
+        private_dir(&dir).unwrap();
+        assert_eq!(mode(&dir), 0o700);
+
+        let open = base.join("open");
+        fs::DirBuilder::new().mode(0o755).create(&open).unwrap();
+        private_dir(&open).unwrap();
+        assert_eq!(mode(&open), 0o700);
+
+        let link = base.join("link");
+        std::os::unix::fs::symlink(&dir, &link).unwrap();
+        assert!(private_dir(&link).is_err());
+
+        let file = base.join("file");
+        fs::write(&file, "").unwrap();
+        assert!(private_dir(&file).is_err());
+
+        fs::remove_dir_all(&base).unwrap();
+    }
+}
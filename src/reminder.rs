@@ -0,0 +1,103 @@
+// Reminders shortly before each filled block, which can be snoozed or answered by
+// joining the meeting. Activities can be muted to never be reminded of.
+
+use chrono::{Duration, NaiveDateTime};
+
+use crate::clock;
+use crate::meeting;
+use crate::storage::{Activity, PersistentData};
+use crate::trigger::{Scheduler, Trigger};
+
+pub const DEFAULT_MINUTES: u32 = 5;
+pub const MAX_MINUTES: u32 = 60;
+pub const SNOOZE_MINUTES: i64 = 5;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Savefile, serde::Serialize, serde::Deserialize)]
+#[serde(default)]
+pub struct ReminderSettings {
+    #[savefile_versions = "12.."]
+    pub enabled: bool,
+
+    // Minutes before the start of the block
+    #[savefile_versions = "12.."]
+    pub minutes: u32,
+}
+
+// Off until the user turns them on, also for schedules from before reminders existed
+impl Default for ReminderSettings {
+    fn default() -> ReminderSettings {
+        ReminderSettings { enabled: false, minutes: DEFAULT_MINUTES }
+    }
+}
+
+fn lead_minutes(settings: &ReminderSettings, activity: &Activity) -> Option<u32> {
+    if settings.enabled && !activity.mute_reminders {
+        Some(settings.minutes)
+    } else {
+        None
+    }
+}
+
+// How the user answered a reminder
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Answer {
+    Join,
+    Snooze,
+    Dismiss,
+}
+
+#[derive(Debug, Default)]
+pub struct Reminders {
+    scheduler: Scheduler,
+    // Reminders to show again, with their new time
+    snoozed: Vec<Trigger>,
+}
+
+impl Reminders {
+    pub fn new() -> Reminders {
+        Reminders::default()
+    }
+
+    // Reminders whose time has come, including snoozed ones
+    pub fn due(&mut self, data: &PersistentData, now: &NaiveDateTime) -> Vec<Trigger> {
+        let mut due = self.scheduler.due(data, now, |activity| { lead_minutes(&data.reminders, activity) });
+        let (snoozed, waiting): (Vec<Trigger>, Vec<Trigger>) = self.snoozed.drain(..)
+            .partition(|trigger| { trigger.at <= *now });
+        self.snoozed = waiting;
+        due.extend(snoozed);
+        due
+    }
+
+    // Remind again later, unless the block is over by then
+    pub fn snooze(&mut self, reminder: &Trigger, now: &NaiveDateTime) {
+        let at = *now + Duration::minutes(SNOOZE_MINUTES);
+        if at < reminder.occurrence.end {
+            self.snoozed.push(Trigger { at, ..*reminder });
+        }
+    }
+}
+
+// Time until the reminder goes away, when its block ends
+pub fn expiry(reminder: &Trigger, now: &NaiveDateTime) -> std::time::Duration {
+    (reminder.occurrence.end - *now).to_std().unwrap_or_default()
+}
+
+// Title and text of the reminder, such as "Math in 5 min" and "10:00-11:30, Zoom 123456789"
+pub fn describe(data: &PersistentData, reminder: &Trigger, now: &NaiveDateTime) -> (String, String) {
+    let activity = data.activities.iter().find(|activity| { activity.id == reminder.activity });
+    let name = activity.map(|activity| { activity.name.as_str() }).unwrap_or("?");
+    let occurrence = &reminder.occurrence;
+
+    let title = if occurrence.start > *now {
+        format!("{} {}", name, clock::describe_start(now, &occurrence.start))
+    } else {
+        format!("{} has started", name)
+    };
+
+    let mut body = format!("{}\u{2013}{}", occurrence.start.format("%H:%M"), occurrence.end.format("%H:%M"));
+    if let Some(link) = activity.and_then(|activity| { meeting::parse(&activity.url) }) {
+        body = format!("{}, {}", body, link.summary());
+    }
+
+    (title, body)
+}
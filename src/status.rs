@@ -0,0 +1,182 @@
+// One-line summary of the current and next activity for status bars such as waybar,
+// i3blocks and polybar.
+
+use chrono::NaiveDateTime;
+
+use crate::clock::{self, Occurrence};
+use crate::storage::{Activity, PersistentData};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum StatusFormat {
+    // JSON object for a custom module with "return-type": "json"
+    Waybar,
+    // Full text, then short text
+    I3blocks,
+    // Just the text, which also suits polybar
+    Plain,
+}
+
+impl StatusFormat {
+    pub fn parse(name: &str) -> Option<StatusFormat> {
+        match name {
+            "waybar" => Some(StatusFormat::Waybar),
+            "i3blocks" => Some(StatusFormat::I3blocks),
+            "plain" | "polybar" => Some(StatusFormat::Plain),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct Status {
+    // "Math, 45 min left" or "Next: Physics in 12 min"
+    pub text: String,
+    // Activity name only, for narrow bars
+    pub short_text: String,
+    // Both the current and the next activity with their times
+    pub tooltip: String,
+    // "current", "upcoming" or "free", for styling
+    pub class: &'static str,
+}
+
+fn planned(data: &PersistentData, occurrence: Option<Occurrence>) -> Option<(&Activity, Occurrence)> {
+    let occurrence = occurrence?;
+    let id = occurrence.activity?;
+    data.activities.iter()
+        .find(|activity| { activity.id == id })
+        .map(|activity| { (activity, occurrence) })
+}
+
+fn times(occurrence: &Occurrence) -> String {
+    format!("{}\u{2013}{}", occurrence.start.format("%H:%M"), occurrence.end.format("%H:%M"))
+}
+
+pub fn status(data: &PersistentData, now: &NaiveDateTime) -> Status {
+    let current = planned(data, clock::current(data, now));
+    let next = planned(data, clock::next(data, now));
+
+    let mut tooltip = vec![];
+    if let Some((activity, occurrence)) = &current {
+        tooltip.push(format!("Now: {} {}", activity.name, times(occurrence)));
+    }
+    if let Some((activity, occurrence)) = &next {
+        tooltip.push(format!("Next: {} {} ({})", activity.name,
+                             clock::describe_start(now, &occurrence.start), times(occurrence)));
+    }
+
+    let tooltip = if tooltip.is_empty() { String::from("Nothing planned") } else { tooltip.join("\n") };
+    match (current, next) {
+        (Some((activity, occurrence)), _) => {
+            let left = clock::minutes_until(now, &occurrence.end);
+            let countdown = if left < 60 {
+                format!("{} min left", left)
+            } else {
+                format!("until {}", occurrence.end.format("%H:%M"))
+            };
+
+            Status {
+                text: format!("{}, {}", activity.name, countdown),
+                short_text: activity.name.clone(),
+                tooltip,
+                class: "current",
+            }
+        }
+        (None, Some((activity, occurrence))) => Status {
+            text: format!("Next: {} {}", activity.name, clock::describe_start(now, &occurrence.start)),
+            short_text: activity.name.clone(),
+            tooltip,
+            class: "upcoming",
+        },
+        (None, None) => Status {
+            text: String::from("Free"),
+            short_text: String::from("Free"),
+            tooltip,
+            class: "free",
+        },
+    }
+}
+
+// Output for the bar. Followed output is one line per update, so i3blocks only
+// gets the full text then.
+pub fn render(status: &Status, format: StatusFormat, follow: bool) -> String {
+    match format {
+        StatusFormat::Waybar => {
+            serde_json::json!({
+                "text": status.text,
+                "alt": status.short_text,
+                "tooltip": status.tooltip,
+                "class": status.class,
+            }).to_string()
+        }
+        StatusFormat::I3blocks if follow => status.text.clone(),
+        StatusFormat::I3blocks => format!("{}\n{}", status.text, status.short_text),
+        StatusFormat::Plain => status.text.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::BlockTime;
+
+    // Math on Mondays 09:00-10:00, then Art until 12:00
+    fn data() -> PersistentData {
+        let mut data = PersistentData::new();
+        data.activities.push(Activity { name: String::from("Math"), id: 0, ..Activity::default() });
+        data.activities.push(Activity { name: String::from("Art"), id: 1, ..Activity::default() });
+        data.block_times = vec![
+            BlockTime { start: 9 * 60, end: 10 * 60 },
+            BlockTime { start: 10 * 60 + 30, end: 12 * 60 },
+        ];
+        data.slots = vec![vec![Some(0), Some(1)]];
+        data
+    }
+
+    // On Monday, 2021-02-01
+    fn monday(time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(&format!("2021-02-01 {}", time), "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    #[test]
+    fn shows_the_next_block_before_the_first() {
+        let status = status(&data(), &monday("08:48"));
+        assert_eq!(status.text, "Next: Math in 12 min");
+        assert_eq!(status.short_text, "Math");
+        assert_eq!(status.tooltip, "Next: Math in 12 min (09:00\u{2013}10:00)");
+        assert_eq!(status.class, "upcoming");
+    }
+
+    #[test]
+    fn counts_down_during_a_block() {
+        let status = status(&data(), &monday("09:15"));
+        assert_eq!(status.text, "Math, 45 min left");
+        assert_eq!(status.short_text, "Math");
+        assert_eq!(status.tooltip, "Now: Math 09:00\u{2013}10:00\nNext: Art at 10:30 (10:30\u{2013}12:00)");
+        assert_eq!(status.class, "current");
+
+        assert_eq!(super::status(&data(), &monday("10:45")).text, "Art, until 12:00");
+    }
+
+    #[test]
+    fn shows_next_week_after_the_last_block() {
+        let status = status(&data(), &monday("12:00"));
+        assert_eq!(status.text, "Next: Math on Monday at 09:00");
+        assert_eq!(status.class, "upcoming");
+
+        let status = super::status(&PersistentData::new(), &monday("12:00"));
+        assert_eq!((status.text.as_str(), status.tooltip.as_str(), status.class), ("Free", "Nothing planned", "free"));
+    }
+
+    #[test]
+    fn renders_for_each_bar() {
+        let status = status(&data(), &monday("09:15"));
+        let waybar: serde_json::Value = serde_json::from_str(&render(&status, StatusFormat::Waybar, false)).unwrap();
+        assert_eq!(waybar["text"], "Math, 45 min left");
+        assert_eq!(waybar["alt"], "Math");
+        assert_eq!(waybar["class"], "current");
+
+        assert_eq!(render(&status, StatusFormat::I3blocks, false), "Math, 45 min left\nMath");
+        assert_eq!(render(&status, StatusFormat::I3blocks, true), "Math, 45 min left");
+        assert_eq!(render(&status, StatusFormat::Plain, false), "Math, 45 min left");
+    }
+}
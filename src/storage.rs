@@ -0,0 +1,809 @@
+use savefile::prelude::SavefileError;
+use std::fmt;
+use std::fs;
+use std::io::Write;
+use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::api::ApiSettings;
+use crate::autojoin::AutoJoinSettings;
+use crate::caldav::{CalDavSettings, SyncedSlot};
+use crate::launcher::Launcher;
+use crate::paths;
+use crate::reminder::ReminderSettings;
+use crate::text_format::TextSchedule;
+
+// Version of the data structures in memory. Every stored field is tagged with
+// the version it was added in, and UPGRADES brings older files up to date.
+pub const CURRENT_VERSION: u32 = 14;
+
+pub const DEFAULT_BACKUP_COUNT: usize = 5;
+pub const MAX_BACKUP_COUNT: usize = 50;
+
+// Don't create a new backup if the newest one is more recent than this
+const BACKUP_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+pub const MINUTES_PER_DAY: u32 = 24 * 60;
+
+#[derive(Debug, Clone, Eq, PartialEq, Default, Savefile, serde::Serialize, serde::Deserialize)]
+pub struct Activity {
+    #[savefile_versions = "1.."]
+    pub name: String,
+    #[savefile_versions = "1.."]
+    pub url: String,
+    #[savefile_versions = "1.."]
+    pub id: usize,
+
+    // Overrides the global launcher for this activity
+    #[savefile_versions = "9.."]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub launcher: Option<Launcher>,
+
+    // Browser profile directory, such as one signed in to the school account
+    #[savefile_versions = "9.."]
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub profile: String,
+
+    // Open recognized meeting links in the provider's app instead of the launcher
+    #[savefile_versions = "10.."]
+    #[serde(default)]
+    pub open_in_app: bool,
+
+    // Join the meeting this many minutes before the block starts, even if
+    // automatic joining is off globally
+    #[savefile_versions = "11.."]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub autojoin: Option<u32>,
+
+    // Never remind of this activity
+    #[savefile_versions = "12.."]
+    #[serde(default)]
+    pub mute_reminders: bool,
+}
+
+// Start and end of a block, in minutes since midnight
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Savefile)]
+pub struct BlockTime {
+    #[savefile_versions = "3.."]
+    pub start: u32,
+    #[savefile_versions = "3.."]
+    pub end: u32,
+}
+
+impl std::fmt::Display for BlockTime {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{}\u{2013}{}", format_clock(self.start), format_clock(self.end))
+    }
+}
+
+pub fn format_clock(minutes: u32) -> String {
+    format!("{:0>2}:{:0>2}", minutes / 60, minutes % 60)
+}
+
+// Parse "HH:MM" into minutes since midnight
+pub fn parse_clock(text: &str) -> Option<u32> {
+    let mut parts = text.trim().splitn(2, ':');
+    let hours: u32 = parts.next()?.trim().parse().ok()?;
+    let minutes: u32 = parts.next()?.trim().parse().ok()?;
+
+    if minutes >= 60 || hours > 24 || hours * 60 + minutes > MINUTES_PER_DAY {
+        return None;
+    }
+
+    Some(hours * 60 + minutes)
+}
+
+// New blocks continue after the last one and keep its length, starting with 08:00-10:00
+pub fn resize_block_times(times: &mut Vec<BlockTime>, blocks: usize) {
+    while times.len() < blocks {
+        let next = match times.last() {
+            Some(last) => {
+                let start = last.end.min(MINUTES_PER_DAY - 1);
+                BlockTime { start, end: (start + last.end - last.start).min(MINUTES_PER_DAY) }
+            }
+            None => BlockTime { start: 8 * 60, end: 10 * 60 },
+        };
+
+        times.push(next);
+    }
+
+    times.truncate(blocks);
+}
+
+// Plan slot as stored by versions 1-4, with a copy of the activity label
+#[derive(Debug, Clone, Eq, PartialEq, Savefile)]
+pub struct LegacySlot {
+    #[savefile_versions = "1.."]
+    pub index: usize,
+    #[savefile_versions = "1.."]
+    pub label: String,
+}
+
+#[derive(Clone, Savefile, Default)]
+pub struct PersistentData {
+    #[savefile_versions = "1.."]
+    pub activities: Vec<Activity>,
+
+    // Version 1 files always had 5 days with 6 blocks each
+    #[savefile_versions = "1..1"]
+    pub plan: [[Option<LegacySlot>; 6]; 5],
+
+    #[savefile_versions = "2..4"]
+    pub grid: Vec<Vec<Option<LegacySlot>>>,
+
+    #[savefile_versions = "3.."]
+    pub block_times: Vec<BlockTime>,
+
+    #[savefile_versions = "4.."]
+    pub next_activity_id: usize,
+
+    #[savefile_versions = "5.."]
+    pub slots: Vec<Vec<Option<usize>>>,
+
+    // Version which wrote the file, 0 for files before version 6
+    #[savefile_versions = "6.."]
+    pub version: u32,
+
+    // Number of backups kept next to the file
+    #[savefile_versions = "7.."]
+    pub backup_count: usize,
+
+    // How meeting links are opened. Older versions always used google-chrome-unstable,
+    // which is missing on most systems, so they get the system default.
+    #[savefile_versions = "8.."]
+    pub launcher: Launcher,
+
+    #[savefile_versions = "11.."]
+    pub autojoin: AutoJoinSettings,
+
+    #[savefile_versions = "12.."]
+    pub reminders: ReminderSettings,
+
+    // HTTP API on localhost
+    #[savefile_versions = "13.."]
+    pub api: ApiSettings,
+
+    #[savefile_versions = "14.."]
+    pub caldav: CalDavSettings,
+
+    // Slots as the CalDAV calendar had them after the last synchronization
+    #[savefile_versions = "14.."]
+    pub caldav_synced: Vec<SyncedSlot>,
+}
+
+impl PersistentData {
+    pub fn new() -> PersistentData {
+        PersistentData {
+            version: CURRENT_VERSION,
+            backup_count: DEFAULT_BACKUP_COUNT,
+            ..PersistentData::default()
+        }
+    }
+}
+
+type Upgrade = fn(&mut PersistentData);
+
+// Each step brings data from the previous version to the given one
+const UPGRADES: &[(u32, Upgrade)] = &[
+    (2, upgrade_to_grid),
+    (3, upgrade_to_block_times),
+    (4, upgrade_to_next_activity_id),
+    (5, upgrade_to_slots),
+    (7, upgrade_to_backup_count),
+];
+
+// Files before version 6 don't record their version, but every version added
+// a field which is never empty once written. The exception is next_activity_id,
+// which stays 0 in a version 4 file without activities. Such a file is taken for
+// version 3, and upgrading it to version 4 computes the same 0 again.
+fn detect_version(data: &PersistentData) -> u32 {
+    if data.version != 0 {
+        data.version
+    } else if !data.slots.is_empty() {
+        5
+    } else if data.next_activity_id != 0 {
+        4
+    } else if !data.block_times.is_empty() {
+        3
+    } else if !data.grid.is_empty() {
+        2
+    } else {
+        1
+    }
+}
+
+fn upgrade_to_grid(data: &mut PersistentData) {
+    data.grid = data.plan.iter().map(|day| { day.to_vec() }).collect();
+}
+
+fn upgrade_to_block_times(data: &mut PersistentData) {
+    let blocks = data.grid.first().map(|day| { day.len() }).unwrap_or(0);
+    resize_block_times(&mut data.block_times, blocks);
+}
+
+// Older versions reused the lowest free ID, so continue after the largest one
+fn upgrade_to_next_activity_id(data: &mut PersistentData) {
+    data.next_activity_id = data.activities.iter()
+        .map(|activity| { activity.id + 1 })
+        .max().unwrap_or(0);
+}
+
+// Labels are looked up when rendering, so stale ones are simply dropped
+fn upgrade_to_slots(data: &mut PersistentData) {
+    let activities = &data.activities;
+    data.slots = data.grid.iter().map(|day| {
+        day.iter().map(|slot| {
+            slot.as_ref()
+                .map(|slot| { slot.index })
+                .filter(|id| { activities.iter().any(|activity| { activity.id == *id }) })
+        }).collect()
+    }).collect();
+}
+
+fn upgrade_to_backup_count(data: &mut PersistentData) {
+    data.backup_count = DEFAULT_BACKUP_COUNT;
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Savefile(SavefileError),
+    Io(std::io::Error),
+    Json(serde_json::Error),
+    TomlRead(toml::de::Error),
+    TomlWrite(toml::ser::Error),
+    Invalid(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Savefile(err) => write!(f, "{:?}", err),
+            Error::Io(err) => write!(f, "{}", err),
+            Error::Json(err) => write!(f, "{}", err),
+            Error::TomlRead(err) => write!(f, "{}", err),
+            Error::TomlWrite(err) => write!(f, "{}", err),
+            Error::Invalid(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl From<SavefileError> for Error {
+    fn from(err: SavefileError) -> Error {
+        Error::Savefile(err)
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Error {
+        Error::Io(err)
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(err: serde_json::Error) -> Error {
+        Error::Json(err)
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(err: toml::de::Error) -> Error {
+        Error::TomlRead(err)
+    }
+}
+
+impl From<toml::ser::Error> for Error {
+    fn from(err: toml::ser::Error) -> Error {
+        Error::TomlWrite(err)
+    }
+}
+
+// The file format is picked by extension, everything else uses savefile
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum Format {
+    Binary,
+    Json,
+    Toml,
+}
+
+impl Format {
+    pub fn of(path: &str) -> Format {
+        let extension = Path::new(path).extension()
+            .map(|ext| { ext.to_string_lossy().to_lowercase() });
+
+        match extension.as_deref() {
+            Some("json") => Format::Json,
+            Some("toml") => Format::Toml,
+            _ => Format::Binary,
+        }
+    }
+}
+
+// Modification time of the schedule, to notice when it is written by another process
+pub fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| { metadata.modified() }).ok()
+}
+
+pub fn load(path: &str) -> Result<PersistentData, Error> {
+    load_as(path, Format::of(path))
+}
+
+fn load_as(path: &str, format: Format) -> Result<PersistentData, Error> {
+    let text: TextSchedule = match format {
+        Format::Binary => return load_binary(path),
+        Format::Json => serde_json::from_reader(std::io::BufReader::new(fs::File::open(path)?))?,
+        Format::Toml => toml::from_str(&fs::read_to_string(path)?)?,
+    };
+
+    text.into_data().map_err(Error::Invalid)
+}
+
+fn load_binary(path: &str) -> Result<PersistentData, Error> {
+    let mut data: PersistentData = savefile::load_file(path, CURRENT_VERSION)?;
+    upgrade(&mut data);
+    Ok(data)
+}
+
+fn upgrade(data: &mut PersistentData) {
+    let version = detect_version(data);
+    for (step_version, step) in UPGRADES {
+        if version < *step_version {
+            step(data);
+        }
+    }
+
+    data.version = CURRENT_VERSION;
+}
+
+// Schedules may hold the CalDAV password, so they and their backups are only
+// readable by the user
+const FILE_MODE: u32 = 0o600;
+
+// Write to a temporary file and rename it over the old one, so that a failed
+// save never leaves a truncated file behind. The old file is kept as a backup.
+pub fn save(path: &str, data: &PersistentData) -> Result<(), Error> {
+    if let Some(dir) = Path::new(path).parent() {
+        if dir != Path::new("") {
+            fs::create_dir_all(dir)?;
+        }
+    }
+
+    let tmp_path = format!("{}.tmp", path);
+    let file = fs::OpenOptions::new().create(true).write(true).truncate(true).mode(FILE_MODE).open(&tmp_path)?;
+    // The mode only applies to new files, a leftover one may have another
+    file.set_permissions(fs::Permissions::from_mode(FILE_MODE))?;
+    let mut writer = std::io::BufWriter::new(file);
+    match Format::of(path) {
+        Format::Binary => savefile::save(&mut writer, CURRENT_VERSION, data)?,
+        Format::Json => serde_json::to_writer_pretty(&mut writer, &TextSchedule::from(data))?,
+        Format::Toml => {
+            // Going through a Value puts plain values before tables, as TOML requires
+            let value = toml::Value::try_from(TextSchedule::from(data))?;
+            writer.write_all(toml::to_string_pretty(&value)?.as_bytes())?
+        }
+    }
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+    drop(writer);
+
+    // A backup is only worth having, the new data is kept even without one
+    if Path::new(path).exists() {
+        if let Err(err) = backup(path, data.backup_count) {
+            eprintln!("Failed to back up {}: {}", path, err);
+        }
+    }
+
+    fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+fn unix_time() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH)
+        .map(|time| { time.as_secs() })
+        .unwrap_or(0)
+}
+
+fn backup(path: &str, count: usize) -> std::io::Result<()> {
+    let existing = backups(path);
+    let newest = existing.first().map(|(time, _)| { *time }).unwrap_or(0);
+
+    let now = unix_time();
+    if count > 0 && now >= newest + BACKUP_INTERVAL.as_secs() {
+        let dir = paths::backup_dir(Path::new(path));
+        let name = Path::new(path).file_name().unwrap_or_default().to_string_lossy();
+        fs::create_dir_all(&dir)?;
+        let backup = dir.join(format!("{}.{}.bak", name, now));
+        fs::copy(path, &backup)?;
+        fs::set_permissions(&backup, fs::Permissions::from_mode(FILE_MODE))?;
+    }
+
+    for (_, old) in backups(path).iter().skip(count) {
+        fs::remove_file(old)?;
+    }
+
+    Ok(())
+}
+
+// Backups of the given schedule as (unix time, path), newest first
+pub fn backups(path: &str) -> Vec<(u64, PathBuf)> {
+    let path = Path::new(path);
+    let prefix = match path.file_name() {
+        Some(name) => format!("{}.", name.to_string_lossy()),
+        None => return vec![],
+    };
+
+    let mut found: Vec<(u64, PathBuf)> = fs::read_dir(paths::backup_dir(path))
+        .map(|entries| {
+            entries.filter_map(|entry| {
+                let entry = entry.ok()?;
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let time = name.strip_prefix(&prefix)?.strip_suffix(".bak")?.parse().ok()?;
+                Some((time, entry.path()))
+            }).collect()
+        })
+        .unwrap_or_default();
+
+    found.sort_by(|a, b| { b.0.cmp(&a.0) });
+    found
+}
+
+// Lossless conversion between formats, e.g. from the binary file to JSON
+pub fn convert(input: &str, output: &str) -> Result<(), Error> {
+    save(output, &load(input)?)
+}
+
+// Move an unreadable file out of the way, so that it isn't overwritten by the next save
+pub fn quarantine(path: &str) -> std::io::Result<PathBuf> {
+    let target = PathBuf::from(format!("{}.{}.corrupt", path, unix_time()));
+    fs::rename(path, &target)?;
+    Ok(target)
+}
+
+// Load the given backup and, if it is readable, make it the current file
+pub fn restore(path: &str, backup: &Path) -> Result<PersistentData, Error> {
+    let data = load_as(&backup.to_string_lossy(), Format::of(path))?;
+    save(path, &data)?;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn activity(id: usize, name: &str) -> Activity {
+        Activity { name: name.to_string(), id, ..Activity::default() }
+    }
+
+    fn legacy(index: usize, label: &str) -> Option<LegacySlot> {
+        Some(LegacySlot { index, label: label.to_string() })
+    }
+
+    // Write the data as savefile did in the given version, then load it like the app
+    fn load_version(name: &str, version: u32, data: PersistentData) -> PersistentData {
+        let dir = std::env::temp_dir().join(format!("timeplan-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(format!("{}.bin", name)).to_string_lossy().into_owned();
+
+        savefile::save_file(&path, version, &data).unwrap();
+        let loaded = load(&path).unwrap();
+        fs::remove_file(&path).ok();
+
+        assert_eq!(loaded.version, CURRENT_VERSION);
+        loaded
+    }
+
+    // Math (0) and Art (2), with Physics (1) deleted but still in the plan
+    fn old_activities() -> Vec<Activity> {
+        vec![activity(0, "Math"), activity(2, "Art")]
+    }
+
+    fn old_grid() -> Vec<Vec<Option<LegacySlot>>> {
+        vec![
+            vec![legacy(0, "Math"), None, legacy(1, "Physics")],
+            vec![None, legacy(2, "Art"), None],
+        ]
+    }
+
+    fn old_block_times() -> Vec<BlockTime> {
+        vec![
+            BlockTime { start: 8 * 60, end: 9 * 60 },
+            BlockTime { start: 9 * 60 + 15, end: 10 * 60 + 15 },
+            BlockTime { start: 10 * 60 + 30, end: 11 * 60 + 30 },
+        ]
+    }
+
+    fn expected_slots() -> Vec<Vec<Option<usize>>> {
+        vec![vec![Some(0), None, None], vec![None, Some(2), None]]
+    }
+
+    #[test]
+    fn upgrades_version_1() {
+        let mut data = PersistentData { activities: old_activities(), ..PersistentData::default() };
+        data.plan[0][0] = legacy(0, "Math");
+        data.plan[4][5] = legacy(2, "Art");
+        data.plan[2][3] = legacy(1, "Physics");
+
+        let data = load_version("v1", 1, data);
+        let mut slots = vec![vec![None; 6]; 5];
+        slots[0][0] = Some(0);
+        slots[4][5] = Some(2);
+        assert_eq!(data.slots, slots);
+        assert_eq!(data.block_times.len(), 6);
+        assert_eq!(data.block_times[0], BlockTime { start: 8 * 60, end: 10 * 60 });
+        assert_eq!(data.block_times[5], BlockTime { start: 18 * 60, end: 20 * 60 });
+        assert_eq!(data.next_activity_id, 3);
+        assert_eq!(data.backup_count, DEFAULT_BACKUP_COUNT);
+    }
+
+    #[test]
+    fn upgrades_version_2() {
+        let data = PersistentData { activities: old_activities(), grid: old_grid(), ..PersistentData::default() };
+
+        let data = load_version("v2", 2, data);
+        assert_eq!(data.slots, expected_slots());
+        let starts: Vec<u32> = data.block_times.iter().map(|time| { time.start }).collect();
+        assert_eq!(starts, vec![8 * 60, 10 * 60, 12 * 60]);
+        assert_eq!(data.next_activity_id, 3);
+    }
+
+    #[test]
+    fn upgrades_version_3() {
+        let data = PersistentData {
+            activities: old_activities(),
+            grid: old_grid(),
+            block_times: old_block_times(),
+            ..PersistentData::default()
+        };
+
+        let data = load_version("v3", 3, data);
+        assert_eq!(data.slots, expected_slots());
+        assert_eq!(data.block_times, old_block_times());
+        assert_eq!(data.next_activity_id, 3);
+    }
+
+    #[test]
+    fn upgrades_version_4() {
+        let data = PersistentData {
+            activities: old_activities(),
+            grid: old_grid(),
+            block_times: old_block_times(),
+            next_activity_id: 10,
+            ..PersistentData::default()
+        };
+
+        let data = load_version("v4", 4, data);
+        assert_eq!(data.slots, expected_slots());
+        assert_eq!(data.block_times, old_block_times());
+        assert_eq!(data.next_activity_id, 10);
+    }
+
+    #[test]
+    fn upgrades_version_4_without_activities() {
+        let data = PersistentData {
+            grid: vec![vec![None, None], vec![None, None]],
+            block_times: old_block_times()[..2].to_vec(),
+            ..PersistentData::default()
+        };
+        assert_eq!(detect_version(&data), 3);
+
+        let data = load_version("v4-empty", 4, data);
+        assert_eq!(data.slots, vec![vec![None, None], vec![None, None]]);
+        assert_eq!(data.block_times, old_block_times()[..2].to_vec());
+        assert_eq!(data.next_activity_id, 0);
+    }
+
+    #[test]
+    fn upgrades_version_5() {
+        let data = PersistentData {
+            activities: old_activities(),
+            block_times: old_block_times(),
+            next_activity_id: 10,
+            slots: expected_slots(),
+            ..PersistentData::default()
+        };
+
+        let data = load_version("v5", 5, data);
+        assert_eq!(data.slots, expected_slots());
+        assert_eq!(data.block_times, old_block_times());
+        assert_eq!(data.next_activity_id, 10);
+        assert_eq!(data.backup_count, DEFAULT_BACKUP_COUNT);
+    }
+
+    #[test]
+    fn upgrades_version_6() {
+        let data = PersistentData {
+            activities: old_activities(),
+            block_times: old_block_times(),
+            next_activity_id: 10,
+            slots: expected_slots(),
+            version: 6,
+            ..PersistentData::default()
+        };
+
+        let data = load_version("v6", 6, data);
+        assert_eq!(data.slots, expected_slots());
+        assert_eq!(data.next_activity_id, 10);
+        assert_eq!(data.backup_count, DEFAULT_BACKUP_COUNT);
+    }
+
+    // A version 7 file as later versions build on it
+    fn data_at(version: u32) -> PersistentData {
+        PersistentData {
+            activities: old_activities(),
+            block_times: old_block_times(),
+            next_activity_id: 10,
+            slots: expected_slots(),
+            version,
+            backup_count: 12,
+            ..PersistentData::default()
+        }
+    }
+
+    #[test]
+    fn upgrades_version_7() {
+        let data = PersistentData { launcher: Launcher::Browser(String::from("firefox")), ..data_at(7) };
+
+        let data = load_version("v7", 7, data);
+        assert_eq!(data.slots, expected_slots());
+        assert_eq!(data.block_times, old_block_times());
+        assert_eq!(data.next_activity_id, 10);
+        assert_eq!(data.backup_count, 12);
+        assert_eq!(data.launcher, Launcher::SystemDefault);
+    }
+
+    #[test]
+    fn upgrades_version_8() {
+        let mut data = PersistentData { launcher: Launcher::Command(String::from("firefox {url}")), ..data_at(8) };
+        data.activities[0].launcher = Some(Launcher::Browser(String::from("chromium")));
+        data.activities[0].profile = String::from("school");
+
+        let data = load_version("v8", 8, data);
+        assert_eq!(data.slots, expected_slots());
+        assert_eq!(data.launcher, Launcher::Command(String::from("firefox {url}")));
+        assert_eq!(data.activities[0].launcher, None);
+        assert_eq!(data.activities[0].profile, "");
+    }
+
+    #[test]
+    fn upgrades_version_9() {
+        let mut data = data_at(9);
+        data.activities[0].launcher = Some(Launcher::Browser(String::from("chromium")));
+        data.activities[0].profile = String::from("school");
+        data.activities[0].open_in_app = true;
+
+        let data = load_version("v9", 9, data);
+        assert_eq!(data.slots, expected_slots());
+        assert_eq!(data.activities[0].launcher, Some(Launcher::Browser(String::from("chromium"))));
+        assert_eq!(data.activities[0].profile, "school");
+        assert!(!data.activities[0].open_in_app);
+    }
+
+    #[test]
+    fn upgrades_version_10() {
+        let mut data = PersistentData { autojoin: AutoJoinSettings { enabled: true, minutes: 2 }, ..data_at(10) };
+        data.activities[0].open_in_app = true;
+        data.activities[0].autojoin = Some(3);
+
+        let data = load_version("v10", 10, data);
+        assert_eq!(data.slots, expected_slots());
+        assert!(data.activities[0].open_in_app);
+        assert_eq!(data.activities[0].autojoin, None);
+        assert_eq!(data.autojoin, AutoJoinSettings::default());
+    }
+
+    #[test]
+    fn upgrades_version_11() {
+        let mut data = PersistentData {
+            autojoin: AutoJoinSettings { enabled: true, minutes: 2 },
+            reminders: ReminderSettings { enabled: true, minutes: 3 },
+            ..data_at(11)
+        };
+        data.activities[0].autojoin = Some(3);
+        data.activities[0].mute_reminders = true;
+
+        let data = load_version("v11", 11, data);
+        assert_eq!(data.slots, expected_slots());
+        assert_eq!(data.autojoin, AutoJoinSettings { enabled: true, minutes: 2 });
+        assert_eq!(data.activities[0].autojoin, Some(3));
+        assert!(!data.activities[0].mute_reminders);
+        assert_eq!(data.reminders, ReminderSettings::default());
+        assert!(!data.reminders.enabled);
+    }
+
+    #[test]
+    fn upgrades_version_12() {
+        let mut data = PersistentData {
+            reminders: ReminderSettings { enabled: true, minutes: 3 },
+            api: ApiSettings { enabled: true, port: 8000 },
+            ..data_at(12)
+        };
+        data.activities[0].mute_reminders = true;
+
+        let data = load_version("v12", 12, data);
+        assert_eq!(data.slots, expected_slots());
+        assert_eq!(data.reminders, ReminderSettings { enabled: true, minutes: 3 });
+        assert!(data.activities[0].mute_reminders);
+        assert_eq!(data.api, ApiSettings::default());
+        assert!(!data.api.enabled);
+    }
+
+    #[test]
+    fn upgrades_version_13() {
+        let data = PersistentData {
+            api: ApiSettings { enabled: true, port: 8000 },
+            caldav: CalDavSettings { enabled: true, ..CalDavSettings::default() },
+            caldav_synced: vec![SyncedSlot { href: String::from("/dav/a.ics"), ..SyncedSlot::default() }],
+            ..data_at(13)
+        };
+
+        let data = load_version("v13", 13, data);
+        assert_eq!(data.slots, expected_slots());
+        assert_eq!(data.api, ApiSettings { enabled: true, port: 8000 });
+        assert_eq!(data.caldav, CalDavSettings::default());
+        assert!(data.caldav_synced.is_empty());
+    }
+
+    #[test]
+    fn keeps_version_14() {
+        let caldav = CalDavSettings {
+            enabled: true,
+            url: String::from("https://example.com/dav/"),
+            username: String::from("me"),
+            password: String::from("secret"),
+            ..CalDavSettings::default()
+        };
+        let synced = vec![SyncedSlot { href: String::from("/dav/a.ics"), ..SyncedSlot::default() }];
+        let data = PersistentData { caldav: caldav.clone(), caldav_synced: synced.clone(), ..data_at(14) };
+
+        let data = load_version("v14", 14, data);
+        assert_eq!(data.slots, expected_slots());
+        assert_eq!(data.caldav, caldav);
+        assert_eq!(data.caldav_synced, synced);
+    }
+
+    #[test]
+    fn keeps_files_private() {
+        let dir = std::env::temp_dir().join(format!("timeplan-test-mode-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("plan.json").to_string_lossy().into_owned();
+        let mode = |path: &Path| { fs::metadata(path).unwrap().permissions().mode() & 0o777 };
+
+        let mut data = PersistentData::new();
+        data.caldav.password = String::from("secret");
+        save(&path, &data).unwrap();
+        assert_eq!(mode(Path::new(&path)), FILE_MODE);
+
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        save(&path, &data).unwrap();
+        assert_eq!(mode(Path::new(&path)), FILE_MODE);
+
+        let backups = backups(&path);
+        assert_eq!(backups.len(), 1);
+        assert_eq!(mode(&backups[0].1), FILE_MODE);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn saves_when_the_backup_fails() {
+        let dir = std::env::temp_dir().join(format!("timeplan-test-backup-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("plan.json").to_string_lossy().into_owned();
+
+        let mut data = PersistentData::new();
+        data.activities = old_activities();
+        data.block_times = old_block_times();
+        data.slots = expected_slots();
+        save(&path, &data).unwrap();
+
+        // Pruning can't remove a directory in place of an old backup
+        fs::create_dir(dir.join("plan.json.1.bak")).unwrap();
+        data.backup_count = 0;
+        data.next_activity_id = 7;
+        save(&path, &data).unwrap();
+
+        assert_eq!(load(&path).unwrap().next_activity_id, 7);
+        assert!(!Path::new(&format!("{}.tmp", path)).exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -124,6 +124,55 @@ impl From<InactiveButton> for Box<dyn iced::button::StyleSheet> {
     }
 }
 
+pub struct CurrentBlock;
+pub struct NowLine;
+
+// Color of the current time line and its label
+pub const NOW_COLOR: iced::Color = iced::Color::from_rgb(0.9, 0.2, 0.2);
+
+impl From<CurrentBlock> for Box<dyn iced::container::StyleSheet> {
+    fn from(_theme: CurrentBlock) -> Self {
+        return current::Container.into();
+    }
+}
+
+impl From<NowLine> for Box<dyn iced::rule::StyleSheet> {
+    fn from(_theme: NowLine) -> Self {
+        return current::Rule.into();
+    }
+}
+
+mod current {
+    use iced::{container, rule, Background, Color};
+
+    pub struct Container;
+
+    impl container::StyleSheet for Container {
+        fn style(&self) -> container::Style {
+            container::Style {
+                background: Some(Background::Color(Color::from_rgba(0.9, 0.2, 0.2, 0.1))),
+                border_radius: 3,
+                border_width: 2,
+                border_color: super::NOW_COLOR,
+                ..container::Style::default()
+            }
+        }
+    }
+
+    pub struct Rule;
+
+    impl rule::StyleSheet for Rule {
+        fn style(&self) -> rule::Style {
+            rule::Style {
+                color: super::NOW_COLOR,
+                width: 2,
+                radius: 1,
+                fill_mode: rule::FillMode::Full,
+            }
+        }
+    }
+}
+
 mod inactive {
     use iced::{button, Background, Color};
 
@@ -0,0 +1,178 @@
+// Human-readable representation of PersistentData, used for .json and .toml files.
+
+use crate::api::ApiSettings;
+use crate::autojoin::{self, AutoJoinSettings};
+use crate::caldav::{self, CalDavSettings, SyncedSlot};
+use crate::launcher::Launcher;
+use crate::reminder::{self, ReminderSettings};
+use crate::storage::{self, Activity, BlockTime, PersistentData};
+use crate::{CAPTIONS, MAX_BLOCKS};
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct TextSchedule {
+    pub version: u32,
+    pub next_activity_id: usize,
+    pub backup_count: usize,
+    #[serde(default)]
+    pub launcher: Launcher,
+    #[serde(default)]
+    pub autojoin: AutoJoinSettings,
+    #[serde(default)]
+    pub reminders: ReminderSettings,
+    #[serde(default)]
+    pub api: ApiSettings,
+    #[serde(default)]
+    pub caldav: CalDavSettings,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub caldav_synced: Vec<SyncedSlot>,
+    pub days: usize,
+    pub blocks: Vec<TextBlock>,
+    #[serde(default)]
+    pub activities: Vec<Activity>,
+    // Only filled slots are listed
+    #[serde(default)]
+    pub assignments: Vec<TextAssignment>,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct TextBlock {
+    pub start: String,
+    pub end: String,
+}
+
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
+pub struct TextAssignment {
+    pub day: usize,
+    pub block: usize,
+    pub activity: usize,
+}
+
+impl From<&PersistentData> for TextSchedule {
+    fn from(data: &PersistentData) -> TextSchedule {
+        let assignments = data.slots.iter().enumerate().flat_map(|(day, slots)| {
+            slots.iter().enumerate().filter_map(move |(block, activity)| {
+                activity.map(|activity| { TextAssignment { day, block, activity } })
+            })
+        }).collect();
+
+        TextSchedule {
+            version: storage::CURRENT_VERSION,
+            next_activity_id: data.next_activity_id,
+            backup_count: data.backup_count,
+            launcher: data.launcher.clone(),
+            autojoin: data.autojoin,
+            reminders: data.reminders,
+            api: data.api,
+            caldav: data.caldav.clone(),
+            caldav_synced: data.caldav_synced.clone(),
+            days: data.slots.len(),
+            blocks: data.block_times.iter().map(|time| {
+                TextBlock {
+                    start: storage::format_clock(time.start),
+                    end: storage::format_clock(time.end),
+                }
+            }).collect(),
+            activities: data.activities.clone(),
+            assignments,
+        }
+    }
+}
+
+impl TextSchedule {
+    pub fn into_data(self) -> Result<PersistentData, String> {
+        if self.version > storage::CURRENT_VERSION {
+            return Err(format!("File is from a newer version ({})", self.version));
+        }
+
+        if self.days == 0 || self.blocks.is_empty() {
+            return Err(String::from("The plan needs at least one day and one block"));
+        }
+
+        if self.days > CAPTIONS.len() || self.blocks.len() > MAX_BLOCKS {
+            return Err(format!("The plan can have at most {} days and {} blocks, not {} and {}",
+                               CAPTIONS.len(), MAX_BLOCKS, self.days, self.blocks.len()));
+        }
+
+        let mut data = PersistentData::new();
+        for (idx, block) in self.blocks.iter().enumerate() {
+            let parse = |text: &str| {
+                storage::parse_clock(text)
+                    .ok_or_else(|| { format!("Block {}: invalid time '{}'", idx + 1, text) })
+            };
+
+            let time = BlockTime { start: parse(&block.start)?, end: parse(&block.end)? };
+            if time.start >= time.end {
+                return Err(format!("Block {}: ends before it starts", idx + 1));
+            }
+
+            data.block_times.push(time);
+        }
+
+        data.slots = vec![vec![None; self.blocks.len()]; self.days];
+        for assignment in self.assignments.iter() {
+            if !self.activities.iter().any(|activity| { activity.id == assignment.activity }) {
+                return Err(format!("Unknown activity {} on day {}, block {}",
+                                   assignment.activity, assignment.day + 1, assignment.block + 1));
+            }
+
+            let slot = data.slots.get_mut(assignment.day)
+                .and_then(|day| { day.get_mut(assignment.block) })
+                .ok_or_else(|| {
+                    format!("Day {}, block {} is outside of the plan",
+                            assignment.day + 1, assignment.block + 1)
+                })?;
+            *slot = Some(assignment.activity);
+        }
+
+        let max_id = self.activities.iter().map(|activity| { activity.id + 1 }).max();
+        data.next_activity_id = self.next_activity_id.max(max_id.unwrap_or(0));
+        data.backup_count = self.backup_count.min(storage::MAX_BACKUP_COUNT);
+        data.activities = self.activities;
+        data.launcher = self.launcher;
+        data.autojoin = AutoJoinSettings {
+            minutes: self.autojoin.minutes.min(autojoin::MAX_MINUTES),
+            ..self.autojoin
+        };
+        data.reminders = ReminderSettings {
+            minutes: self.reminders.minutes.min(reminder::MAX_MINUTES),
+            ..self.reminders
+        };
+        data.api = self.api;
+        data.caldav = CalDavSettings {
+            minutes: self.caldav.minutes.clamp(1, caldav::MAX_MINUTES),
+            ..self.caldav
+        };
+        data.caldav_synced = self.caldav_synced;
+        Ok(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schedule(days: usize, blocks: usize) -> String {
+        let block = r#"{"start": "08:00", "end": "09:00"}"#;
+        format!(r#"{{"version": {}, "next_activity_id": 0, "backup_count": 5, "days": {}, "blocks": [{}]}}"#,
+                storage::CURRENT_VERSION, days, vec![block; blocks].join(", "))
+    }
+
+    fn load(name: &str, text: &str) -> Result<PersistentData, String> {
+        let path = std::env::temp_dir().join(format!("timeplan-test-{}-{}.json", name, std::process::id()));
+        std::fs::write(&path, text).unwrap();
+        let result = storage::load(&path.to_string_lossy()).map_err(|err| { err.to_string() });
+        std::fs::remove_file(&path).ok();
+        result
+    }
+
+    #[test]
+    fn refuses_plans_out_of_range() {
+        let data = load("week", &schedule(7, MAX_BLOCKS)).unwrap();
+        assert_eq!((data.slots.len(), data.block_times.len()), (7, MAX_BLOCKS));
+
+        assert_eq!(load("days", &schedule(8, 2)).err(),
+                   Some(format!("The plan can have at most 7 days and {} blocks, not 8 and 2", MAX_BLOCKS)));
+        assert!(load("blocks", &schedule(5, MAX_BLOCKS + 1)).is_err());
+        assert!(load("empty", &schedule(0, 2)).is_err());
+    }
+}
@@ -0,0 +1,175 @@
+// Actions which happen some minutes before a block starts, such as reminders and
+// automatic joins. Each occurrence of a block triggers at most once.
+
+use chrono::{Duration, NaiveDateTime};
+
+use crate::clock::{self, Occurrence};
+use crate::storage::{Activity, PersistentData};
+
+// Checks further apart than this mean the machine was asleep or the clock jumped
+const MAX_CHECK_GAP_SECONDS: i64 = 30;
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct Trigger {
+    pub occurrence: Occurrence,
+    // ID of the activity in the block
+    pub activity: usize,
+    pub at: NaiveDateTime,
+}
+
+impl Trigger {
+    fn key(&self) -> (usize, usize, NaiveDateTime) {
+        (self.occurrence.day, self.occurrence.block, self.occurrence.start)
+    }
+
+    // Whole seconds until the trigger, for countdowns
+    pub fn seconds_left(&self, now: &NaiveDateTime) -> i64 {
+        (self.at - *now).num_seconds().max(0)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    // Time of the previous check. Triggers are due when a check passes their time,
+    // so nothing happens for times before the app was started.
+    last_check: Option<NaiveDateTime>,
+
+    // Occurrences which were triggered or dismissed, as (day, block, start)
+    handled: Vec<(usize, usize, NaiveDateTime)>,
+}
+
+impl Scheduler {
+    pub fn new() -> Scheduler {
+        Scheduler::default()
+    }
+
+    // Triggers after the given time, soonest first. `lead` gives the minutes before the
+    // start of the block for activities which have the action.
+    fn planned(&self, data: &PersistentData, after: &NaiveDateTime,
+               lead: impl Fn(&Activity) -> Option<u32>) -> Vec<Trigger> {
+        let mut triggers: Vec<Trigger> = clock::upcoming(data, after).into_iter()
+            .filter_map(|occurrence| {
+                let activity = data.activities.iter()
+                    .find(|activity| { Some(activity.id) == occurrence.activity })?;
+                let at = occurrence.start - Duration::minutes(lead(activity)? as i64);
+                Some(Trigger { occurrence, activity: activity.id, at })
+            })
+            .filter(|trigger| { trigger.at > *after && !self.handled.contains(&trigger.key()) })
+            .collect();
+
+        triggers.sort_by_key(|trigger| { trigger.at });
+        triggers
+    }
+
+    // Next trigger within the given number of seconds
+    pub fn next_within(&self, data: &PersistentData, now: &NaiveDateTime, seconds: i64,
+                       lead: impl Fn(&Activity) -> Option<u32>) -> Option<Trigger> {
+        let horizon = *now + Duration::seconds(seconds);
+        self.planned(data, now, lead).into_iter().find(|trigger| { trigger.at <= horizon })
+    }
+
+    // Triggers whose time has come since the previous check, which are marked as handled.
+    // If the machine was asleep at the time, they only happen if the block hasn't started yet.
+    pub fn due(&mut self, data: &PersistentData, now: &NaiveDateTime,
+               lead: impl Fn(&Activity) -> Option<u32>) -> Vec<Trigger> {
+        let last = match self.last_check.replace(*now) {
+            Some(last) => last,
+            None => return vec![],
+        };
+
+        let awake = *now >= last && *now - last <= Duration::seconds(MAX_CHECK_GAP_SECONDS);
+        let due: Vec<Trigger> = self.planned(data, &last, lead).into_iter()
+            .filter(|trigger| { trigger.at <= *now && (awake || trigger.occurrence.start > *now) })
+            .collect();
+
+        self.handled.extend(due.iter().map(|trigger| { trigger.key() }));
+        self.handled.retain(|(_, _, start)| { *start + Duration::days(1) > *now });
+        due
+    }
+
+    // Don't trigger the occurrence anymore
+    pub fn dismiss(&mut self, trigger: &Trigger) {
+        self.handled.push(trigger.key());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::BlockTime;
+
+    // Math on Mondays 09:00-10:00
+    fn data() -> PersistentData {
+        let mut data = PersistentData::new();
+        data.activities.push(Activity { name: String::from("Math"), id: 0, ..Activity::default() });
+        data.block_times = vec![BlockTime { start: 9 * 60, end: 10 * 60 }];
+        data.slots = vec![vec![Some(0)]];
+        data
+    }
+
+    // On Monday, 2021-02-01
+    fn monday(time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(&format!("2021-02-01 {}", time), "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn five_minutes(_: &Activity) -> Option<u32> {
+        Some(5)
+    }
+
+    #[test]
+    fn triggers_once_per_occurrence() {
+        let data = data();
+        let mut scheduler = Scheduler::new();
+
+        // Nothing before the first check
+        assert!(scheduler.due(&data, &monday("08:54:58"), five_minutes).is_empty());
+        assert!(scheduler.due(&data, &monday("08:54:59"), five_minutes).is_empty());
+
+        let due = scheduler.due(&data, &monday("08:55:00"), five_minutes);
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].activity, 0);
+        assert_eq!(due[0].at, monday("08:55:00"));
+        assert_eq!(due[0].occurrence.start, monday("09:00:00"));
+
+        assert!(scheduler.due(&data, &monday("08:55:00"), five_minutes).is_empty());
+        assert!(scheduler.due(&data, &monday("08:55:01"), five_minutes).is_empty());
+        assert!(scheduler.due(&data, &monday("09:30:00"), five_minutes).is_empty());
+    }
+
+    #[test]
+    fn skips_blocks_which_started_while_asleep() {
+        let data = data();
+
+        // Woken up before the block starts, which is still worth it
+        let mut scheduler = Scheduler::new();
+        scheduler.due(&data, &monday("08:50:00"), five_minutes);
+        assert_eq!(scheduler.due(&data, &monday("08:58:00"), five_minutes).len(), 1);
+
+        // Woken up during the block
+        let mut scheduler = Scheduler::new();
+        scheduler.due(&data, &monday("08:50:00"), five_minutes);
+        let gap = Duration::seconds(MAX_CHECK_GAP_SECONDS + 1);
+        assert!(scheduler.due(&data, &(monday("09:00:00") + gap), five_minutes).is_empty());
+        assert!(scheduler.due(&data, &(monday("09:00:01") + gap), five_minutes).is_empty());
+    }
+
+    #[test]
+    fn skips_dismissed_occurrences() {
+        let data = data();
+        let mut scheduler = Scheduler::new();
+        scheduler.due(&data, &monday("08:54:00"), five_minutes);
+
+        let next = scheduler.next_within(&data, &monday("08:54:30"), 60, five_minutes).unwrap();
+        assert_eq!(next.seconds_left(&monday("08:54:30")), 30);
+        assert!(scheduler.next_within(&data, &monday("08:53:00"), 60, five_minutes).is_none());
+
+        scheduler.dismiss(&next);
+        assert!(scheduler.next_within(&data, &monday("08:54:30"), 60, five_minutes).is_none());
+        assert!(scheduler.due(&data, &monday("08:55:00"), five_minutes).is_empty());
+
+        // Next week's occurrence is another one
+        let week_later = monday("08:55:00") + Duration::weeks(1);
+        scheduler.due(&data, &(week_later - Duration::seconds(1)), five_minutes);
+        assert_eq!(scheduler.due(&data, &week_later, five_minutes).len(), 1);
+    }
+}